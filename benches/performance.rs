@@ -19,6 +19,7 @@ fn benchmark_config_operations(c: &mut Criterion) {
                 account_id: "123456789012".to_string(),
                 source_profile: None,
                 session_duration: Some(3600),
+                ..Default::default()
             };
             config.add_role(black_box(role));
             black_box(config);
@@ -33,6 +34,7 @@ fn benchmark_config_operations(c: &mut Criterion) {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
         config.add_role(role);
     }
@@ -72,6 +74,7 @@ fn benchmark_config_io(c: &mut Criterion) {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
         config.add_role(role);
     }
@@ -99,6 +102,7 @@ fn benchmark_role_operations(c: &mut Criterion) {
                 account_id: black_box("123456789012".to_string()),
                 source_profile: None,
                 session_duration: Some(3600),
+                ..Default::default()
             };
             black_box(role);
         });
@@ -115,6 +119,7 @@ fn benchmark_role_operations(c: &mut Criterion) {
                     account_id: "123456789012".to_string(),
                     source_profile: None,
                     session_duration: Some(3600),
+                    ..Default::default()
                 };
                 config.add_role(role);
             }
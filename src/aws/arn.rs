@@ -0,0 +1,159 @@
+//! Partition-aware ARN parsing.
+
+use crate::error::{AppError, AppResult};
+use std::fmt;
+
+/// An AWS partition: a group of regions with its own ARN prefix and endpoints.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Partition {
+    #[default]
+    Aws,
+    AwsCn,
+    AwsUsGov,
+}
+
+impl Partition {
+    pub fn parse(name: &str) -> AppResult<Self> {
+        match name {
+            "aws" => Ok(Partition::Aws),
+            "aws-cn" => Ok(Partition::AwsCn),
+            "aws-us-gov" => Ok(Partition::AwsUsGov),
+            other => Err(AppError::ConfigError(format!(
+                "Unsupported AWS partition '{}'",
+                other
+            ))),
+        }
+    }
+
+    /// The partition a region belongs to.
+    pub fn of_region(region: &str) -> Self {
+        if region.starts_with("cn-") {
+            Partition::AwsCn
+        } else if region.starts_with("us-gov-") {
+            Partition::AwsUsGov
+        } else {
+            Partition::Aws
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Partition::Aws => "aws",
+            Partition::AwsCn => "aws-cn",
+            Partition::AwsUsGov => "aws-us-gov",
+        }
+    }
+
+    /// Region used for STS when nothing else names one.
+    pub fn default_region(&self) -> &'static str {
+        match self {
+            Partition::Aws => "us-east-1",
+            Partition::AwsCn => "cn-north-1",
+            Partition::AwsUsGov => "us-gov-west-1",
+        }
+    }
+
+    /// Reject a region outside this partition, which STS would fail to resolve.
+    pub fn validate_region(&self, region: &str) -> AppResult<()> {
+        let region_partition = Partition::of_region(region);
+        if region_partition == *self {
+            Ok(())
+        } else {
+            Err(AppError::ConfigError(format!(
+                "Region '{}' belongs to partition '{}', not '{}'",
+                region, region_partition, self
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The parts of `arn:PARTITION:SERVICE:REGION:ACCOUNT:RESOURCE`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arn {
+    pub partition: Partition,
+    pub service: String,
+    pub region: String,
+    pub account_id: String,
+    pub resource: String,
+}
+
+impl Arn {
+    pub fn parse(arn: &str) -> AppResult<Self> {
+        let invalid = || AppError::ConfigError(format!("Invalid ARN '{}'", arn));
+        let parts: Vec<&str> = arn.splitn(6, ':').collect();
+        if parts.len() != 6 || parts[0] != "arn" || parts[2].is_empty() || parts[5].is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            partition: Partition::parse(parts[1])?,
+            service: parts[2].to_string(),
+            region: parts[3].to_string(),
+            account_id: parts[4].to_string(),
+            resource: parts[5].to_string(),
+        })
+    }
+
+    /// Name of the IAM role behind a `role/...` or `assumed-role/...` ARN,
+    /// ignoring any role path.
+    pub fn role_name(&self) -> Option<&str> {
+        if let Some(path) = self.resource.strip_prefix("role/") {
+            path.rsplit('/').next()
+        } else if let Some(rest) = self.resource.strip_prefix("assumed-role/") {
+            rest.split('/').next()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_partitions() {
+        let arn = Arn::parse("arn:aws-us-gov:iam::123456789012:role/path/to/Admin").unwrap();
+        assert_eq!(arn.partition, Partition::AwsUsGov);
+        assert_eq!(arn.service, "iam");
+        assert_eq!(arn.account_id, "123456789012");
+        assert_eq!(arn.role_name(), Some("Admin"));
+
+        let arn = Arn::parse("arn:aws-cn:sts::123456789012:assumed-role/Admin/me").unwrap();
+        assert_eq!(arn.partition, Partition::AwsCn);
+        assert_eq!(arn.role_name(), Some("Admin"));
+
+        let arn = Arn::parse("arn:aws:iam::123456789012:user/alice").unwrap();
+        assert_eq!(arn.partition, Partition::Aws);
+        assert_eq!(arn.role_name(), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Arn::parse("not-an-arn").is_err());
+        assert!(Arn::parse("arn:aws:iam::123456789012").is_err());
+        assert!(Arn::parse("arn:aws-mars:iam::123456789012:role/X").is_err());
+    }
+
+    #[test]
+    fn test_region_partition() {
+        assert_eq!(Partition::of_region("us-gov-east-1"), Partition::AwsUsGov);
+        assert_eq!(Partition::of_region("cn-northwest-1"), Partition::AwsCn);
+        assert_eq!(Partition::of_region("eu-west-1"), Partition::Aws);
+
+        assert!(Partition::AwsUsGov.validate_region("us-gov-west-1").is_ok());
+        let err = Partition::AwsUsGov
+            .validate_region("us-east-1")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Region 'us-east-1' belongs to partition 'aws', not 'aws-us-gov'"
+        );
+    }
+}
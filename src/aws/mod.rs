@@ -1,14 +1,24 @@
+pub mod arn;
+pub mod profile;
+pub mod sso;
+
 use crate::config::RoleConfig;
 use crate::error::{AppError, AppResult};
 use aws_config::SdkConfig;
 use aws_sdk_sso::Client as SsoClient;
+use aws_sdk_sts::error::{DisplayErrorContext, SdkError};
+use aws_sdk_sts::operation::assume_role::{AssumeRoleError, AssumeRoleOutput};
+use aws_sdk_sts::types::{PolicyDescriptorType, Tag};
 use aws_sdk_sts::Client as StsClient;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+#[derive(Clone)]
 pub struct AwsClient {
     sts_client: StsClient,
-    #[allow(dead_code)]
     sso_client: SsoClient,
+    /// SourceIdentity of the role session this client signs with, which every
+    /// later hop of a role chain must repeat
+    source_identity: Option<String>,
 }
 
 #[derive(Debug)]
@@ -17,6 +27,105 @@ pub struct Credentials {
     pub secret_access_key: String,
     pub session_token: Option<String>,
     pub expiration: Option<SystemTime>,
+    /// ARN of the assumed role session, when known
+    pub assumed_role_arn: Option<String>,
+    /// SourceIdentity the session carries, when known
+    pub source_identity: Option<String>,
+}
+
+/// Per-call parameters for an AssumeRole request.
+#[derive(Debug, Default)]
+pub struct AssumeRoleOptions {
+    /// Session duration in seconds (default: 3600)
+    pub duration_seconds: Option<i32>,
+    /// Current TOTP code for the role's `mfa_serial`
+    pub mfa_token: Option<String>,
+    /// Rendered RoleSessionName; a fixed default is used when unset
+    pub session_name: Option<String>,
+    /// Rendered session tags as `(key, value)` pairs
+    pub tags: Vec<(String, String)>,
+    /// Keys of `tags` passed on to later hops of a role chain
+    pub transitive_tag_keys: Vec<String>,
+    /// Minified inline session policy JSON
+    pub policy: Option<String>,
+    /// Managed session policy ARNs
+    pub policy_arns: Vec<String>,
+    /// Rendered SourceIdentity
+    pub source_identity: Option<String>,
+}
+
+impl AssumeRoleOptions {
+    fn sts_tags(&self) -> Option<Vec<Tag>> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        let tags = self
+            .tags
+            .iter()
+            .filter_map(|(key, value)| Tag::builder().key(key).value(value).build().ok())
+            .collect();
+        Some(tags)
+    }
+
+    fn sts_transitive_tag_keys(&self) -> Option<Vec<String>> {
+        (!self.transitive_tag_keys.is_empty()).then(|| self.transitive_tag_keys.clone())
+    }
+
+    fn sts_policy_arns(&self) -> Option<Vec<PolicyDescriptorType>> {
+        if self.policy_arns.is_empty() {
+            return None;
+        }
+        let arns = self
+            .policy_arns
+            .iter()
+            .map(|arn| PolicyDescriptorType::builder().arn(arn).build())
+            .collect();
+        Some(arns)
+    }
+}
+
+/// Where and how the STS and SSO clients connect.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClientSettings {
+    /// Explicit region; otherwise the environment, the profile, or the
+    /// partition's default region
+    pub region: Option<String>,
+    pub partition: arn::Partition,
+    pub use_fips_endpoint: Option<bool>,
+    pub use_dualstack_endpoint: Option<bool>,
+    /// Endpoint for both STS and SSO, e.g. LocalStack or an internal proxy
+    pub endpoint_url: Option<String>,
+}
+
+impl ClientSettings {
+    fn apply(&self, mut loader: aws_config::ConfigLoader) -> aws_config::ConfigLoader {
+        if let Some(use_fips) = self.use_fips_endpoint {
+            loader = loader.use_fips(use_fips);
+        }
+        if let Some(use_dual_stack) = self.use_dualstack_endpoint {
+            loader = loader.use_dual_stack(use_dual_stack);
+        }
+        if let Some(endpoint_url) = &self.endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        loader
+    }
+}
+
+/// A permission set assigned to the signed-in SSO user in one account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsoAccountRole {
+    pub account_id: String,
+    pub account_name: String,
+    pub role_name: String,
+}
+
+/// Outcome of [`AwsClient::test_assume_role`].
+#[derive(Debug)]
+pub enum RoleAccess {
+    Allowed,
+    /// STS refused the request; the error says why and how to fix it
+    Denied(AppError),
 }
 
 #[derive(Debug)]
@@ -27,22 +136,79 @@ pub struct CallerIdentity {
 }
 
 impl AwsClient {
-    pub async fn new() -> AppResult<Self> {
-        // Check if region is already configured via environment or AWS config
-        let config_builder = aws_config::defaults(aws_config::BehaviorVersion::latest());
+    /// Build a client whose base credentials come from the named AWS profile.
+    ///
+    /// `None` uses the default credential chain. A profile that is not defined
+    /// in `~/.aws/config` or `~/.aws/credentials` is rejected up front rather than
+    /// surfacing later as an opaque SDK credentials error.
+    ///
+    /// STS is called in `settings.region` when given (a configured role or global
+    /// region), otherwise in the region from the environment, the profile, or the
+    /// default region of `settings.partition`.
+    pub async fn with_profile(profile: Option<&str>, settings: &ClientSettings) -> AppResult<Self> {
+        let mut config_builder = aws_config::defaults(aws_config::BehaviorVersion::latest());
+        let mut profile_region = None;
+
+        if let Some(profile_name) = profile {
+            let profiles = profile::Profiles::load()?;
+            let properties = profiles.get(profile_name).ok_or_else(|| {
+                AppError::ConfigError(format!(
+                    "AWS profile '{}' not found in ~/.aws/config or ~/.aws/credentials",
+                    profile_name
+                ))
+            })?;
+            profile_region = properties.get("region").cloned();
+            config_builder = config_builder.profile_name(profile_name);
+        }
 
         // If no region is explicitly set, use a default to prevent IMDS timeout
-        let config = if std::env::var("AWS_REGION").is_err()
-            && std::env::var("AWS_DEFAULT_REGION").is_err()
-        {
-            config_builder
-                .region("us-east-1") // Default region to prevent IMDS timeout
-                .load()
-                .await
-        } else {
-            config_builder.load().await
+        let region = match &settings.region {
+            Some(region) => Some(region.clone()),
+            None if std::env::var("AWS_REGION").is_err()
+                && std::env::var("AWS_DEFAULT_REGION").is_err() =>
+            {
+                Some(
+                    profile_region
+                        .unwrap_or_else(|| settings.partition.default_region().to_string()),
+                )
+            }
+            None => None,
         };
-        Ok(Self::new_with_config(&config))
+        if let Some(region) = region {
+            config_builder = config_builder.region(aws_config::Region::new(region));
+        }
+        let config_builder = settings.apply(config_builder);
+        Ok(Self::new_with_config(&config_builder.load().await))
+    }
+
+    /// Build a client that signs with `credentials`, e.g. the previous hop of a
+    /// role chain, in `settings.region` or else the same region as this one.
+    pub async fn with_credentials(
+        &self,
+        credentials: &Credentials,
+        settings: &ClientSettings,
+    ) -> Self {
+        let provider = aws_sdk_sts::config::Credentials::new(
+            &credentials.access_key_id,
+            &credentials.secret_access_key,
+            credentials.session_token.clone(),
+            credentials.expiration,
+            "aws-assume-role",
+        );
+        let mut config_builder = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .credentials_provider(provider);
+        let region = match &settings.region {
+            Some(region) => Some(aws_config::Region::new(region.clone())),
+            None => self.sts_client.config().region().cloned(),
+        };
+        if let Some(region) = region {
+            config_builder = config_builder.region(region);
+        }
+        let config_builder = settings.apply(config_builder);
+        Self {
+            source_identity: credentials.source_identity.clone(),
+            ..Self::new_with_config(&config_builder.load().await)
+        }
     }
 
     pub fn new_with_config(config: &SdkConfig) -> Self {
@@ -52,26 +218,55 @@ impl AwsClient {
         Self {
             sts_client,
             sso_client,
+            source_identity: None,
         }
     }
 
+    /// SourceIdentity already set on this client's session, if any.
+    pub fn source_identity(&self) -> Option<&str> {
+        self.source_identity.as_deref()
+    }
+
     pub async fn assume_role(
         &self,
         role_config: &RoleConfig,
-        duration_seconds: Option<i32>,
+        options: &AssumeRoleOptions,
     ) -> AppResult<Credentials> {
-        let duration = duration_seconds.unwrap_or(3600);
+        let duration = options.duration_seconds.unwrap_or(3600);
+        let session_name = options
+            .session_name
+            .as_deref()
+            .unwrap_or("aws-assume-role-session");
+        validate_role_session_name(session_name)?;
+        validate_session_tags(&options.tags, &options.transitive_tag_keys)?;
+        validate_policy_arns(&options.policy_arns)?;
+        if let Some(source_identity) = &options.source_identity {
+            validate_source_identity(source_identity)?;
+        }
 
-        let assume_role_result = self
-            .sts_client
-            .assume_role()
-            .role_arn(&role_config.role_arn)
-            .role_session_name("aws-assume-role-session")
-            .duration_seconds(duration)
-            .send()
+        let assume_role_result = match self
+            .send_assume_role(role_config, options, session_name, duration)
             .await
-            .map_err(|e| AppError::AwsError(format!("Failed to assume role: {}", e)))?;
+        {
+            Err(e) if is_max_session_duration_error(&e) && duration > 3600 => {
+                // A TOTP code is accepted once, so retrying would be rejected anyway
+                if options.mfa_token.is_some() {
+                    return Err(AppError::AwsError(format!(
+                        "Requested duration {}s exceeds the MaxSessionDuration of role '{}'; \
+                         request a shorter --duration or lower its session_duration",
+                        duration, role_config.name
+                    )));
+                }
+                self.retry_at_max_duration(role_config, options, session_name, duration)
+                    .await?
+            }
+            result => result.map_err(|e| assume_role_error(&e, options))?,
+        };
 
+        let source_identity = assume_role_result
+            .source_identity
+            .or_else(|| options.source_identity.clone());
+        let assumed_role_arn = assume_role_result.assumed_role_user.map(|user| user.arn);
         let credentials = assume_role_result
             .credentials
             .ok_or_else(|| AppError::AwsError("No credentials returned".to_string()))?;
@@ -84,9 +279,88 @@ impl AwsClient {
             secret_access_key: credentials.secret_access_key,
             session_token: Some(credentials.session_token),
             expiration,
+            assumed_role_arn,
+            source_identity,
         })
     }
 
+    async fn send_assume_role(
+        &self,
+        role_config: &RoleConfig,
+        options: &AssumeRoleOptions,
+        session_name: &str,
+        duration: i32,
+    ) -> Result<AssumeRoleOutput, SdkError<AssumeRoleError>> {
+        self.sts_client
+            .assume_role()
+            .role_arn(&role_config.role_arn)
+            .role_session_name(session_name)
+            .duration_seconds(duration)
+            .set_external_id(role_config.external_id.clone())
+            .set_serial_number(role_config.mfa_serial.clone())
+            .set_token_code(options.mfa_token.clone())
+            .set_tags(options.sts_tags())
+            .set_transitive_tag_keys(options.sts_transitive_tag_keys())
+            .set_policy(options.policy.clone())
+            .set_policy_arns(options.sts_policy_arns())
+            .set_source_identity(options.source_identity.clone())
+            .send()
+            .await
+    }
+
+    /// STS does not reveal a role's MaxSessionDuration, so binary search the
+    /// whole hours below the requested duration for the longest one accepted
+    /// (at most four attempts), starting from one hour, which every role allows.
+    /// Each accepted attempt issues a session; only the longest is kept.
+    async fn retry_at_max_duration(
+        &self,
+        role_config: &RoleConfig,
+        options: &AssumeRoleOptions,
+        session_name: &str,
+        requested: i32,
+    ) -> AppResult<AssumeRoleOutput> {
+        let mut lowest_hours = 1;
+        let mut highest_hours = (requested - 1) / 3600;
+        let mut accepted = None;
+        while lowest_hours < highest_hours {
+            let hours = (lowest_hours + highest_hours + 1) / 2;
+            match self
+                .send_assume_role(role_config, options, session_name, hours * 3600)
+                .await
+            {
+                Ok(output) => {
+                    lowest_hours = hours;
+                    accepted = Some(output);
+                }
+                Err(e) if is_max_session_duration_error(&e) => highest_hours = hours - 1,
+                Err(e) => return Err(assume_role_error(&e, options)),
+            }
+        }
+
+        let duration = lowest_hours * 3600;
+        let output = match accepted {
+            Some(output) => output,
+            None => self
+                .send_assume_role(role_config, options, session_name, duration)
+                .await
+                .map_err(|e| {
+                    if is_max_session_duration_error(&e) {
+                        AppError::AwsError(format!(
+                            "STS rejected even a one-hour session for role '{}'",
+                            role_config.name
+                        ))
+                    } else {
+                        assume_role_error(&e, options)
+                    }
+                })?,
+        };
+        eprintln!(
+            "⚠️  Requested duration {}s exceeds the MaxSessionDuration of role '{}'; using {}s",
+            requested, role_config.name, duration
+        );
+        Ok(output)
+    }
+
     /// Verify current AWS credentials and identity
     pub async fn verify_current_identity(&self) -> AppResult<CallerIdentity> {
         let result = self
@@ -104,48 +378,58 @@ impl AwsClient {
     }
 
     /// Test if we can assume a specific role (dry run)
-    pub async fn test_assume_role(&self, role_config: &RoleConfig) -> AppResult<bool> {
+    ///
+    /// `options.duration_seconds` is ignored; the test always requests the minimum duration.
+    pub async fn test_assume_role(
+        &self,
+        role_config: &RoleConfig,
+        options: &AssumeRoleOptions,
+    ) -> AppResult<RoleAccess> {
         // Check if we're already in an assumed role session
         if let Ok(current_identity) = self.verify_current_identity().await {
             // If we're already in an assumed role session and it's the same role being tested
-            if current_identity.arn.contains("assumed-role") {
-                // Extract the role name from the current ARN
-                // ARN format: arn:aws:sts::123456789012:assumed-role/role-name/session-name
-                if let Some(role_part) = current_identity.arn.split('/').nth(1) {
-                    // Extract the role name from the target role ARN
-                    // ARN format: arn:aws:iam::123456789012:role/role-name
-                    if let Some(target_role_name) = role_config.role_arn.split('/').next_back() {
-                        if role_part == target_role_name {
-                            // We're already using this role - verification successful
-                            return Ok(true);
-                        }
-                    }
+            // ARN format: arn:PARTITION:sts::ACCOUNT:assumed-role/role-name/session-name
+            if let (Ok(current), Ok(target)) = (
+                arn::Arn::parse(&current_identity.arn),
+                arn::Arn::parse(&role_config.role_arn),
+            ) {
+                if current.resource.starts_with("assumed-role/")
+                    && current.partition == target.partition
+                    && current.account_id == target.account_id
+                    && current.role_name().is_some()
+                    && current.role_name() == target.role_name()
+                {
+                    // We're already using this role - verification successful
+                    return Ok(RoleAccess::Allowed);
                 }
             }
         }
 
-        // Proceed with normal assumption test if not already in the target role
+        let session_name = options
+            .session_name
+            .as_deref()
+            .unwrap_or("aws-assume-role-test");
+        validate_role_session_name(session_name)?;
+        validate_session_tags(&options.tags, &options.transitive_tag_keys)?;
+        validate_policy_arns(&options.policy_arns)?;
+        if let Some(source_identity) = &options.source_identity {
+            validate_source_identity(source_identity)?;
+        }
+
+        // Proceed with normal assumption test if not already in the target role,
+        // requesting the minimum duration
         match self
-            .sts_client
-            .assume_role()
-            .role_arn(&role_config.role_arn)
-            .role_session_name("aws-assume-role-test")
-            .duration_seconds(900) // Minimum duration for test
-            .send()
+            .send_assume_role(role_config, options, session_name, 900)
             .await
         {
-            Ok(_) => Ok(true),
-            Err(e) => {
-                let error_msg = e.to_string();
-                if error_msg.contains("AccessDenied") || error_msg.contains("Forbidden") {
-                    Ok(false)
-                } else {
-                    Err(AppError::AwsError(format!(
-                        "Failed to test role assumption: {}",
-                        e
-                    )))
-                }
+            Ok(_) => Ok(RoleAccess::Allowed),
+            Err(e) if is_access_denied(&e) => {
+                Ok(RoleAccess::Denied(assume_role_error(&e, options)))
             }
+            Err(e) => Err(AppError::AwsError(format!(
+                "Failed to test role assumption: {}",
+                DisplayErrorContext(&e)
+            ))),
         }
     }
 
@@ -159,19 +443,18 @@ impl AwsClient {
         }
     }
 
-    #[allow(dead_code)]
     pub async fn get_sso_credentials(
         &self,
         account_id: &str,
         role_name: &str,
-        access_token: &str,
+        token: &sso::SsoToken,
     ) -> AppResult<Credentials> {
         let role_creds = self
-            .sso_client
+            .sso_client_for(token)
             .get_role_credentials()
             .role_name(role_name)
             .account_id(account_id)
-            .access_token(access_token)
+            .access_token(&token.access_token)
             .send()
             .await
             .map_err(|e| {
@@ -189,6 +472,621 @@ impl AwsClient {
             expiration: Some(
                 UNIX_EPOCH + std::time::Duration::from_secs(role_creds.expiration as u64),
             ),
+            assumed_role_arn: None,
+            source_identity: None,
         })
     }
+
+    /// Every account and permission set the token's user can access.
+    pub async fn list_sso_account_roles(
+        &self,
+        token: &sso::SsoToken,
+    ) -> AppResult<Vec<SsoAccountRole>> {
+        let sso_client = self.sso_client_for(token);
+        let accounts = sso_client
+            .list_accounts()
+            .access_token(&token.access_token)
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await
+            .map_err(|e| {
+                AppError::AwsError(format!(
+                    "Failed to list SSO accounts: {}",
+                    aws_sdk_sso::error::DisplayErrorContext(&e)
+                ))
+            })?;
+
+        let mut roles = Vec::new();
+        for account in accounts {
+            let account_id = account.account_id().unwrap_or_default();
+            let account_roles = sso_client
+                .list_account_roles()
+                .access_token(&token.access_token)
+                .account_id(account_id)
+                .into_paginator()
+                .items()
+                .send()
+                .try_collect()
+                .await
+                .map_err(|e| {
+                    AppError::AwsError(format!(
+                        "Failed to list SSO roles for account {}: {}",
+                        account_id,
+                        aws_sdk_sso::error::DisplayErrorContext(&e)
+                    ))
+                })?;
+
+            roles.extend(account_roles.into_iter().map(|role| SsoAccountRole {
+                account_id: account_id.to_string(),
+                account_name: account.account_name().unwrap_or(account_id).to_string(),
+                role_name: role.role_name().unwrap_or_default().to_string(),
+            }));
+        }
+
+        Ok(roles)
+    }
+
+    /// The SSO portal API lives in the Identity Center region, not the session region.
+    fn sso_client_for(&self, token: &sso::SsoToken) -> SsoClient {
+        SsoClient::from_conf(
+            self.sso_client
+                .config()
+                .to_builder()
+                .region(aws_config::Region::new(token.region.clone()))
+                .build(),
+        )
+    }
+}
+
+/// Format a timestamp as ISO-8601 UTC, e.g. `2024-01-01T12:00:00Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    aws_sdk_sts::primitives::DateTime::from(time)
+        .fmt(aws_sdk_sts::primitives::DateTimeFormat::DateTime)
+        .unwrap_or_default()
+}
+
+fn is_max_session_duration_error(error: &SdkError<AssumeRoleError>) -> bool {
+    error
+        .as_service_error()
+        .and_then(|e| e.meta().message())
+        .is_some_and(|message| message.contains("MaxSessionDuration"))
+}
+
+fn assume_role_error(error: &SdkError<AssumeRoleError>, options: &AssumeRoleOptions) -> AppError {
+    let mut message = format!("Failed to assume role: {}", DisplayErrorContext(error));
+    if let (true, Some(source_identity)) = (is_access_denied(error), &options.source_identity) {
+        message.push_str(&format!(
+            " (SourceIdentity '{}' was requested: the role's trust policy must allow sts:SetSourceIdentity, or set source_identity to \"\" to disable it)",
+            source_identity
+        ));
+    }
+    AppError::AwsError(message)
+}
+
+fn is_access_denied(error: &SdkError<AssumeRoleError>) -> bool {
+    error
+        .as_service_error()
+        .and_then(|e| e.meta().code())
+        .is_some_and(|code| code == "AccessDenied")
+}
+
+fn is_session_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+=,.@-".contains(c)
+}
+
+/// Check a RoleSessionName against the STS rules: 2-64 characters from `[\w+=,.@-]`.
+pub fn validate_role_session_name(name: &str) -> AppResult<()> {
+    if !(2..=64).contains(&name.len()) {
+        return Err(AppError::ConfigError(format!(
+            "Invalid session name '{}': must be 2-64 characters long",
+            name
+        )));
+    }
+    if let Some(c) = name.chars().find(|c| !is_session_name_char(*c)) {
+        return Err(AppError::ConfigError(format!(
+            "Invalid session name '{}': character '{}' is not allowed (use letters, digits and _+=,.@-)",
+            name, c
+        )));
+    }
+    Ok(())
+}
+
+/// STS accepts at most this many session tags per AssumeRole call.
+pub const MAX_SESSION_TAGS: usize = 50;
+
+fn is_session_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c.is_whitespace() || "_.:/=+-@".contains(c)
+}
+
+/// Check session tags against the STS limits: at most 50 tags, keys of 1-128
+/// and values of up to 256 characters from `[\p{L}\p{Z}\p{N}_.:/=+\-@]`, keys
+/// unique ignoring case, and every transitive key naming one of the tags.
+pub fn validate_session_tags(
+    tags: &[(String, String)],
+    transitive_keys: &[String],
+) -> AppResult<()> {
+    if tags.len() > MAX_SESSION_TAGS {
+        return Err(AppError::ConfigError(format!(
+            "Too many session tags: {} (STS allows at most {})",
+            tags.len(),
+            MAX_SESSION_TAGS
+        )));
+    }
+
+    for (i, (key, value)) in tags.iter().enumerate() {
+        let invalid = |reason: String| {
+            AppError::ConfigError(format!("Invalid session tag '{}': {}", key, reason))
+        };
+        if !(1..=128).contains(&key.chars().count()) {
+            return Err(invalid("key must be 1-128 characters long".to_string()));
+        }
+        if value.chars().count() > 256 {
+            return Err(invalid(
+                "value must be at most 256 characters long".to_string(),
+            ));
+        }
+        if let Some(c) = key
+            .chars()
+            .chain(value.chars())
+            .find(|c| !is_session_tag_char(*c))
+        {
+            return Err(invalid(format!(
+                "character '{}' is not allowed (use letters, digits, spaces and _.:/=+-@)",
+                c
+            )));
+        }
+        if tags[..i]
+            .iter()
+            .any(|(other, _)| other.eq_ignore_ascii_case(key))
+        {
+            return Err(invalid(
+                "duplicate key (tag keys are case-insensitive)".to_string(),
+            ));
+        }
+    }
+
+    if let Some(key) = transitive_keys
+        .iter()
+        .find(|key| !tags.iter().any(|(tag, _)| tag.eq_ignore_ascii_case(key)))
+    {
+        return Err(AppError::ConfigError(format!(
+            "Transitive tag key '{}' is not one of the session tags",
+            key
+        )));
+    }
+    Ok(())
+}
+
+/// STS rejects inline session policies longer than this many characters.
+pub const MAX_SESSION_POLICY_LENGTH: usize = 2048;
+
+/// STS accepts at most this many managed session policies.
+pub const MAX_POLICY_ARNS: usize = 10;
+
+/// Load an inline session policy given as JSON or `@path`, check that it is a
+/// JSON object, and return it minified so whitespace does not count against
+/// the 2048-character limit.
+pub fn resolve_session_policy(source: &str) -> AppResult<String> {
+    let (json, origin) = match source.strip_prefix('@') {
+        Some(path) => {
+            let json = std::fs::read_to_string(path).map_err(|e| {
+                AppError::ConfigError(format!(
+                    "Failed to read session policy file '{}': {}",
+                    path, e
+                ))
+            })?;
+            (json, format!("session policy file '{}'", path))
+        }
+        None => (source.to_string(), "session policy".to_string()),
+    };
+
+    let policy: serde_json::Value = serde_json::from_str(&json)
+        .map_err(|e| AppError::ConfigError(format!("Invalid {}: {}", origin, e)))?;
+    if !policy.is_object() {
+        return Err(AppError::ConfigError(format!(
+            "Invalid {}: expected a JSON object",
+            origin
+        )));
+    }
+
+    let minified = policy.to_string();
+    let length = minified.chars().count();
+    if length > MAX_SESSION_POLICY_LENGTH {
+        return Err(AppError::ConfigError(format!(
+            "Invalid {}: {} characters after removing whitespace (STS allows at most {})",
+            origin, length, MAX_SESSION_POLICY_LENGTH
+        )));
+    }
+    Ok(minified)
+}
+
+/// Check managed session policy ARNs: at most 10 IAM policy ARNs.
+pub fn validate_policy_arns(policy_arns: &[String]) -> AppResult<()> {
+    if policy_arns.len() > MAX_POLICY_ARNS {
+        return Err(AppError::ConfigError(format!(
+            "Too many session policy ARNs: {} (STS allows at most {})",
+            policy_arns.len(),
+            MAX_POLICY_ARNS
+        )));
+    }
+    for policy_arn in policy_arns {
+        let is_policy = arn::Arn::parse(policy_arn)
+            .is_ok_and(|arn| arn.service == "iam" && arn.resource.starts_with("policy/"));
+        if !is_policy {
+            return Err(AppError::ConfigError(format!(
+                "Invalid session policy ARN '{}': expected arn:PARTITION:iam::ACCOUNT:policy/NAME",
+                policy_arn
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check a SourceIdentity against the STS rules: 2-64 characters from `[\w+=,.@-]`.
+pub fn validate_source_identity(value: &str) -> AppResult<()> {
+    let invalid = |reason: String| {
+        AppError::ConfigError(format!("Invalid source identity '{}': {}", value, reason))
+    };
+    if !(2..=64).contains(&value.len()) {
+        return Err(invalid("must be 2-64 characters long".to_string()));
+    }
+    if let Some(c) = value.chars().find(|c| !is_session_name_char(*c)) {
+        return Err(invalid(format!(
+            "character '{}' is not allowed (use letters, digits and _+=,.@-)",
+            c
+        )));
+    }
+    Ok(())
+}
+
+/// Replace characters STS does not accept in a session name with `-`.
+pub fn sanitize_session_name_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if is_session_name_char(c) { c } else { '-' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_role_session_name() {
+        assert!(validate_role_session_name("aws-assume-role-session").is_ok());
+        assert!(validate_role_session_name("alice@laptop,dev=1.2+x_y").is_ok());
+        assert!(validate_role_session_name("a").is_err());
+        assert!(validate_role_session_name(&"a".repeat(64)).is_ok());
+        assert!(validate_role_session_name(&"a".repeat(65)).is_err());
+        assert!(validate_role_session_name("has space").is_err());
+        assert!(validate_role_session_name("colon:name").is_err());
+    }
+
+    #[test]
+    fn test_validate_session_tags() {
+        let tag = |key: &str, value: &str| (key.to_string(), value.to_string());
+        let tags = vec![tag("team", "platform"), tag("cost-center", "cc:1234/ops")];
+        assert!(validate_session_tags(&tags, &["Team".to_string()]).is_ok());
+        assert!(validate_session_tags(&[tag("owner", "")], &[]).is_ok());
+
+        let err = validate_session_tags(&tags, &["ticket".to_string()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Transitive tag key 'ticket' is not one of the session tags"
+        );
+        assert!(validate_session_tags(&[tag("", "x")], &[]).is_err());
+        assert!(validate_session_tags(&[tag(&"k".repeat(129), "x")], &[]).is_err());
+        assert!(validate_session_tags(&[tag("k", &"v".repeat(257))], &[]).is_err());
+        assert!(validate_session_tags(&[tag("k", "a,b")], &[]).is_err());
+        assert!(validate_session_tags(&[tag("Team", "a"), tag("team", "b")], &[]).is_err());
+
+        let many: Vec<_> = (0..51).map(|i| tag(&format!("k{}", i), "v")).collect();
+        assert!(validate_session_tags(&many[..50], &[]).is_ok());
+        assert!(validate_session_tags(&many, &[]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_session_policy() {
+        let policy = resolve_session_policy(
+            r#"{
+                "Version": "2012-10-17",
+                "Statement": [{"Effect": "Allow", "Action": "s3:GetObject", "Resource": "*"}]
+            }"#,
+        )
+        .unwrap();
+        assert!(!policy.contains(char::is_whitespace));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("policy.json");
+        std::fs::write(&path, "{\"Version\": \"2012-10-17\"}").unwrap();
+        assert_eq!(
+            resolve_session_policy(&format!("@{}", path.display())).unwrap(),
+            "{\"Version\":\"2012-10-17\"}"
+        );
+
+        assert!(resolve_session_policy("{\"Version\":").is_err());
+        assert!(resolve_session_policy("[]").is_err());
+        assert!(resolve_session_policy("@/nonexistent/policy.json").is_err());
+
+        let long = format!("{{\"Sid\":\"{}\"}}", "x".repeat(MAX_SESSION_POLICY_LENGTH));
+        let err = resolve_session_policy(&long).unwrap_err();
+        assert!(err.to_string().contains("STS allows at most 2048"));
+    }
+
+    #[test]
+    fn test_validate_policy_arns() {
+        assert!(validate_policy_arns(&[
+            "arn:aws:iam::aws:policy/ReadOnlyAccess".to_string(),
+            "arn:aws-us-gov:iam::123456789012:policy/team/Scoped".to_string(),
+        ])
+        .is_ok());
+        assert!(validate_policy_arns(&["arn:aws:iam::123456789012:role/Dev".to_string()]).is_err());
+        assert!(validate_policy_arns(&["ReadOnlyAccess".to_string()]).is_err());
+
+        let many = vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_string(); 11];
+        assert!(validate_policy_arns(&many).is_err());
+    }
+
+    #[test]
+    fn test_validate_source_identity() {
+        assert!(validate_source_identity("alice").is_ok());
+        assert!(validate_source_identity("alice@example.com").is_ok());
+        assert!(validate_source_identity("a").is_err());
+        assert!(validate_source_identity(&"a".repeat(65)).is_err());
+        assert!(validate_source_identity("alice smith").is_err());
+        assert!(validate_source_identity("AWS:alice").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        assert_eq!(format_timestamp(time), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn test_sanitize_session_name_value() {
+        assert_eq!(
+            sanitize_session_name_value("AROAEXAMPLE:alice smith"),
+            "AROAEXAMPLE-alice-smith"
+        );
+        assert_eq!(sanitize_session_name_value("josé"), "jos-");
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_url_setting() {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=GetCallerIdentity"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "<GetCallerIdentityResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+                 <GetCallerIdentityResult>\
+                 <Arn>arn:aws:sts::000000000000:assumed-role/Dev/ci</Arn>\
+                 <UserId>AROAEXAMPLE:ci</UserId>\
+                 <Account>000000000000</Account>\
+                 </GetCallerIdentityResult>\
+                 </GetCallerIdentityResponse>",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let base = AwsClient::new_with_config(
+            &aws_config::defaults(aws_config::BehaviorVersion::latest())
+                .region(aws_config::Region::new("us-east-1"))
+                .no_credentials()
+                .load()
+                .await,
+        );
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            expiration: None,
+            assumed_role_arn: None,
+            source_identity: None,
+        };
+        let settings = ClientSettings {
+            endpoint_url: Some(server.uri()),
+            ..Default::default()
+        };
+        let client = base.with_credentials(&credentials, &settings).await;
+
+        let identity = client.verify_current_identity().await.unwrap();
+        assert_eq!(identity.account, "000000000000");
+        assert_eq!(
+            identity.arn,
+            "arn:aws:sts::000000000000:assumed-role/Dev/ci"
+        );
+    }
+
+    /// An STS stand-in that only accepts one-hour sessions
+    /// An STS stand-in for a role whose MaxSessionDuration is `max_duration`,
+    /// expecting `calls` AssumeRole requests. The access key names the duration.
+    async fn limited_sts(max_duration: i32, calls: u64) -> (wiremock::MockServer, AwsClient) {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("Action=AssumeRole"))
+            .respond_with(move |request: &wiremock::Request| {
+                let duration: i32 = String::from_utf8_lossy(&request.body)
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("DurationSeconds="))
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(3600);
+                if duration > max_duration {
+                    return ResponseTemplate::new(400).set_body_string(
+                        "<ErrorResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+                         <Error><Type>Sender</Type><Code>ValidationError</Code>\
+                         <Message>The requested DurationSeconds exceeds the MaxSessionDuration set for this role.</Message>\
+                         </Error><RequestId>1</RequestId></ErrorResponse>",
+                    );
+                }
+                ResponseTemplate::new(200).set_body_string(format!(
+                    "<AssumeRoleResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+                     <AssumeRoleResult>\
+                     <Credentials>\
+                     <AccessKeyId>ASIA{}</AccessKeyId>\
+                     <SecretAccessKey>secret</SecretAccessKey>\
+                     <SessionToken>token</SessionToken>\
+                     <Expiration>2100-01-01T00:00:00Z</Expiration>\
+                     </Credentials>\
+                     </AssumeRoleResult>\
+                     </AssumeRoleResponse>",
+                    duration
+                ))
+            })
+            .expect(calls)
+            .mount(&server)
+            .await;
+
+        let client = AwsClient::new_with_config(
+            &aws_config::defaults(aws_config::BehaviorVersion::latest())
+                .region(aws_config::Region::new("us-east-1"))
+                .endpoint_url(server.uri())
+                .credentials_provider(aws_sdk_sts::config::Credentials::for_tests())
+                .load()
+                .await,
+        );
+        (server, client)
+    }
+
+    fn dev_role() -> RoleConfig {
+        RoleConfig {
+            name: "dev".to_string(),
+            role_arn: "arn:aws:iam::000000000000:role/Dev".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_duration_retry_finds_role_maximum() {
+        // 12h is rejected, then 6h is accepted, 9h rejected, 7h and 8h accepted
+        let (_server, client) = limited_sts(28800, 5).await;
+        let options = AssumeRoleOptions {
+            duration_seconds: Some(43200),
+            ..Default::default()
+        };
+
+        let credentials = client.assume_role(&dev_role(), &options).await.unwrap();
+        assert_eq!(credentials.access_key_id, "ASIA28800");
+    }
+
+    #[tokio::test]
+    async fn test_max_duration_retries_are_capped() {
+        // 12h is rejected, then 6h, 3h and 2h, then 1h is accepted
+        let (_server, client) = limited_sts(3600, 5).await;
+        let options = AssumeRoleOptions {
+            duration_seconds: Some(43200),
+            ..Default::default()
+        };
+
+        let credentials = client.assume_role(&dev_role(), &options).await.unwrap();
+        assert_eq!(credentials.access_key_id, "ASIA3600");
+    }
+
+    #[tokio::test]
+    async fn test_max_duration_with_mfa_is_not_retried() {
+        let (_server, client) = limited_sts(3600, 1).await;
+        let role = RoleConfig {
+            name: "prod".to_string(),
+            role_arn: "arn:aws:iam::000000000000:role/Prod".to_string(),
+            mfa_serial: Some("arn:aws:iam::000000000000:mfa/user".to_string()),
+            ..Default::default()
+        };
+        let options = AssumeRoleOptions {
+            duration_seconds: Some(7200),
+            mfa_token: Some("123456".to_string()),
+            ..Default::default()
+        };
+
+        let err = client.assume_role(&role, &options).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Requested duration 7200s exceeds the MaxSessionDuration of role 'prod'"));
+    }
+
+    #[tokio::test]
+    async fn test_list_sso_account_roles() {
+        use wiremock::matchers::{header, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/assignment/accounts"))
+            .and(header("x-amz-sso_bearer_token", "access-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "accountList": [
+                    {"accountId": "111111111111", "accountName": "Production"},
+                    {"accountId": "222222222222"}
+                ]
+            })))
+            .mount(&server)
+            .await;
+        for (account_id, roles) in [
+            ("111111111111", vec!["Admin", "ReadOnly"]),
+            ("222222222222", vec!["Admin"]),
+        ] {
+            let role_list: Vec<_> = roles
+                .iter()
+                .map(|role| serde_json::json!({"roleName": role, "accountId": account_id}))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path("/assignment/roles"))
+                .and(query_param("account_id", account_id))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(serde_json::json!({ "roleList": role_list })),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-1"))
+            .endpoint_url(server.uri())
+            .no_credentials()
+            .load()
+            .await;
+        let token = sso::SsoToken {
+            start_url: "https://example.awsapps.com/start".to_string(),
+            region: "eu-west-1".to_string(),
+            access_token: "access-token".to_string(),
+            expires_at: "2099-01-01T00:00:00Z".to_string(),
+            refresh_token: None,
+            client_id: None,
+            client_secret: None,
+            registration_expires_at: None,
+        };
+
+        let roles = AwsClient::new_with_config(&config)
+            .list_sso_account_roles(&token)
+            .await
+            .unwrap();
+        let names: Vec<(&str, &str, &str)> = roles
+            .iter()
+            .map(|r| {
+                (
+                    r.account_id.as_str(),
+                    r.account_name.as_str(),
+                    r.role_name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("111111111111", "Production", "Admin"),
+                ("111111111111", "Production", "ReadOnly"),
+                // Accounts without a name fall back to the id
+                ("222222222222", "222222222222", "Admin"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,724 @@
+//! Access to the AWS shared config files (`~/.aws/config` and
+//! `~/.aws/credentials`).
+//!
+//! Only the bits awsr needs are parsed: section headers and `key = value`
+//! pairs. Nested sub-properties (indented lines such as `s3 =` blocks) are
+//! ignored. Writes never touch sections awsr does not own.
+
+use crate::aws::arn::Arn;
+use crate::config::{home_dir, validate_session_duration, Config, RoleConfig, RoleKind};
+use crate::error::{AppError, AppResult};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// First line of the block of profiles managed by `awsr export-profiles --merge`.
+pub const MANAGED_BLOCK_BEGIN: &str = "# BEGIN aws-assume-role managed profiles";
+/// Last line of the managed block.
+pub const MANAGED_BLOCK_END: &str = "# END aws-assume-role managed profiles";
+
+/// Properties of a single profile, keyed by lower-cased property name.
+pub type ProfileProperties = BTreeMap<String, String>;
+
+/// All profiles found in the shared config and credentials files.
+#[derive(Debug, Default)]
+pub struct Profiles {
+    profiles: BTreeMap<String, ProfileProperties>,
+}
+
+impl Profiles {
+    /// Load profiles from the shared config and credentials files.
+    ///
+    /// Missing files are treated as empty. Properties from the credentials
+    /// file take precedence over the config file, as in the AWS CLI.
+    pub fn load() -> AppResult<Self> {
+        let mut profiles = Self::default();
+
+        if let Some(content) = read_optional(&config_file_path()?)? {
+            profiles.merge(parse_profiles(&content, true));
+        }
+        if let Some(content) = read_optional(&credentials_file_path()?)? {
+            profiles.merge(parse_profiles(&content, false));
+        }
+
+        Ok(profiles)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProfileProperties> {
+        self.profiles.get(name)
+    }
+
+    fn merge(&mut self, other: BTreeMap<String, ProfileProperties>) {
+        for (name, properties) in other {
+            self.profiles.entry(name).or_default().extend(properties);
+        }
+    }
+}
+
+/// Path of the shared config file, honouring `AWS_CONFIG_FILE`.
+pub fn config_file_path() -> AppResult<PathBuf> {
+    if let Ok(path) = std::env::var("AWS_CONFIG_FILE") {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(home_dir()?.join(".aws").join("config"))
+}
+
+/// Path of the shared credentials file, honouring `AWS_SHARED_CREDENTIALS_FILE`.
+pub fn credentials_file_path() -> AppResult<PathBuf> {
+    if let Ok(path) = std::env::var("AWS_SHARED_CREDENTIALS_FILE") {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(home_dir()?.join(".aws").join("credentials"))
+}
+
+pub fn read_optional(path: &Path) -> AppResult<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .map_err(|e| AppError::ConfigError(format!("Failed to read {}: {}", path.display(), e)))
+}
+
+/// Map a section header to a profile name.
+///
+/// In the config file profiles are written `[profile name]` (except
+/// `[default]`); in the credentials file the header is the bare name. Other
+/// config sections such as `[sso-session x]` are not profiles.
+fn profile_name_from_header(header: &str, is_config_file: bool) -> Option<String> {
+    let header = header.trim();
+    if !is_config_file || header == "default" {
+        return Some(header.to_string());
+    }
+    header
+        .strip_prefix("profile")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map(|rest| rest.trim().to_string())
+}
+
+/// Parse the profiles in an INI document.
+pub fn parse_profiles(content: &str, is_config_file: bool) -> BTreeMap<String, ProfileProperties> {
+    let mut profiles: BTreeMap<String, ProfileProperties> = BTreeMap::new();
+    let mut current: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            current = profile_name_from_header(header, is_config_file);
+            if let Some(name) = &current {
+                profiles.entry(name.clone()).or_default();
+            }
+            continue;
+        }
+
+        // Indented lines belong to a nested property block
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        if let (Some(name), Some((key, value))) = (&current, trimmed.split_once('=')) {
+            profiles
+                .entry(name.clone())
+                .or_default()
+                .insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    profiles
+}
+
+/// How exported profiles obtain credentials.
+pub enum ExportMode {
+    /// Let the AWS CLI/SDK assume the role itself (`role_arn` + `source_profile`)
+    AssumeRole,
+    /// Call back into awsr through `credential_process`
+    CredentialProcess { command: String },
+}
+
+/// Render a `[profile <name>]` section for every configured role.
+pub fn render_profiles<'a>(
+    config: &Config,
+    roles: impl IntoIterator<Item = &'a RoleConfig>,
+    mode: &ExportMode,
+) -> String {
+    let mut output = String::new();
+
+    for role in roles {
+        output.push_str(&format!("[profile {}]\n", role.name));
+        match mode {
+            // The AWS CLI resolves SSO profiles natively
+            ExportMode::AssumeRole if role.kind == RoleKind::Sso => {
+                if let Some(start_url) = &config.sso_start_url {
+                    output.push_str(&format!("sso_start_url = {}\n", start_url));
+                }
+                if let Some(region) = &config.sso_region {
+                    output.push_str(&format!("sso_region = {}\n", region));
+                }
+                output.push_str(&format!("sso_account_id = {}\n", role.account_id));
+                if let Some(role_name) = &role.sso_role_name {
+                    output.push_str(&format!("sso_role_name = {}\n", role_name));
+                }
+            }
+            ExportMode::AssumeRole => {
+                output.push_str(&format!("role_arn = {}\n", role.role_arn));
+                // The AWS CLI needs an explicit source; awsr's "default chain" maps to `default`.
+                // A chained role sources the parent role's exported profile.
+                let source_profile = match &role.parent_role {
+                    Some(parent_role) => parent_role.as_str(),
+                    None => config.source_profile_for(role).unwrap_or("default"),
+                };
+                output.push_str(&format!("source_profile = {}\n", source_profile));
+                if let Some(duration) = role.session_duration.or(config.default_duration) {
+                    output.push_str(&format!("duration_seconds = {}\n", duration));
+                }
+                if let Some(mfa_serial) = &role.mfa_serial {
+                    output.push_str(&format!("mfa_serial = {}\n", mfa_serial));
+                }
+                if let Some(external_id) = &role.external_id {
+                    output.push_str(&format!("external_id = {}\n", external_id));
+                }
+            }
+            ExportMode::CredentialProcess { command } => {
+                output.push_str(&format!(
+                    "credential_process = {} assume {} --format credential-process\n",
+                    quote_arg(command),
+                    quote_arg(&role.name)
+                ));
+            }
+        }
+        if let Some(region) = config.region_for(role) {
+            output.push_str(&format!("region = {}\n", region));
+        }
+        if let Some(use_fips) = role.use_fips_endpoint {
+            output.push_str(&format!("use_fips_endpoint = {}\n", use_fips));
+        }
+        if let Some(use_dualstack) = role.use_dualstack_endpoint {
+            output.push_str(&format!("use_dualstack_endpoint = {}\n", use_dualstack));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Map an AWS CLI role profile to a role configuration.
+///
+/// Returns `Ok(None)` for profiles without a `role_arn`.
+pub fn role_from_profile(
+    name: &str,
+    properties: &ProfileProperties,
+) -> AppResult<Option<RoleConfig>> {
+    let role_arn = match properties.get("role_arn") {
+        Some(role_arn) => role_arn,
+        None => return Ok(None),
+    };
+
+    let account_id = Arn::parse(role_arn)
+        .ok()
+        .map(|arn| arn.account_id)
+        .filter(|account| !account.is_empty())
+        .ok_or_else(|| {
+            AppError::ConfigError(format!(
+                "Invalid role_arn '{}' in profile '{}'",
+                role_arn, name
+            ))
+        })?;
+
+    let session_duration = properties
+        .get("duration_seconds")
+        .map(|value| -> AppResult<i64> {
+            let seconds = value.parse::<i64>().map_err(|_| {
+                AppError::ConfigError(format!(
+                    "Invalid duration_seconds '{}' in profile '{}'",
+                    value, name
+                ))
+            })?;
+            validate_session_duration(seconds)
+                .map_err(|e| e.with_context(&format!("Profile '{}'", name)))?;
+            Ok(seconds)
+        })
+        .transpose()?;
+    let flag = |key: &str| {
+        properties
+            .get(key)
+            .map(|value| match value.to_ascii_lowercase().as_str() {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(AppError::ConfigError(format!(
+                    "Invalid {} '{}' in profile '{}'",
+                    key, value, name
+                ))),
+            })
+            .transpose()
+    };
+
+    Ok(Some(RoleConfig {
+        name: name.to_string(),
+        role_arn: role_arn.clone(),
+        account_id,
+        source_profile: properties.get("source_profile").cloned(),
+        session_duration,
+        mfa_serial: properties.get("mfa_serial").cloned(),
+        external_id: properties.get("external_id").cloned(),
+        session_name: properties.get("role_session_name").cloned(),
+        region: properties.get("region").cloned(),
+        use_fips_endpoint: flag("use_fips_endpoint")?,
+        use_dualstack_endpoint: flag("use_dualstack_endpoint")?,
+        ..Default::default()
+    }))
+}
+
+/// Quote an argument for the AWS CLI's `credential_process` command parsing.
+fn quote_arg(arg: &str) -> String {
+    if arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+/// The parts of a config file outside the managed block.
+pub fn user_owned_content(content: &str) -> AppResult<String> {
+    let (before, after) = split_managed_block(content)?;
+    Ok(format!("{}{}", before, after))
+}
+
+/// Replace (or append) the managed block in `content` with `profiles`.
+pub fn merge_managed_block(content: &str, profiles: &str) -> AppResult<String> {
+    let (before, after) = split_managed_block(content)?;
+
+    let mut output = before.to_string();
+    if !output.is_empty() && !output.ends_with("\n\n") {
+        output.push_str(if output.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+    output.push_str(MANAGED_BLOCK_BEGIN);
+    output.push('\n');
+    output.push_str(profiles.trim_end());
+    output.push('\n');
+    output.push_str(MANAGED_BLOCK_END);
+    output.push('\n');
+    output.push_str(after);
+    Ok(output)
+}
+
+/// Split `content` into the text before and after the managed block (markers excluded).
+///
+/// A block without its end marker is an error: there is no telling where the
+/// user's own content resumes, and guessing would delete it.
+fn split_managed_block(content: &str) -> AppResult<(&str, &str)> {
+    let begin = match content.find(MANAGED_BLOCK_BEGIN) {
+        Some(begin) => begin,
+        None => return Ok((content, "")),
+    };
+    let after_begin = &content[begin..];
+    let end = after_begin.find(MANAGED_BLOCK_END).ok_or_else(|| {
+        AppError::ConfigError(
+            "The awsr managed block is missing its end marker; fix ~/.aws/config manually"
+                .to_string(),
+        )
+    })?;
+    let rest = &after_begin[end + MANAGED_BLOCK_END.len()..];
+    Ok((&content[..begin], rest.strip_prefix('\n').unwrap_or(rest)))
+}
+
+/// Insert or replace the section `[name]` in an INI document.
+///
+/// The old section body is replaced up to its last setting; comments and blank
+/// lines directly above the next header are kept with that header. Everything
+/// else in the document is preserved verbatim.
+pub fn upsert_section(content: &str, name: &str, body: &[String]) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let is_header = |line: &str| {
+        let trimmed = line.trim();
+        trimmed.starts_with('[') && trimmed.ends_with(']')
+    };
+    let is_trivia = |line: &str| {
+        let trimmed = line.trim();
+        trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';')
+    };
+
+    let mut section = vec![format!("[{}]", name)];
+    section.extend(body.iter().cloned());
+
+    let start = lines
+        .iter()
+        .position(|line| is_header(line) && line.trim()[1..line.trim().len() - 1].trim() == name);
+
+    let mut output: Vec<String> = Vec::new();
+    match start {
+        Some(start) => {
+            let next_header = lines[start + 1..]
+                .iter()
+                .position(|line| is_header(line))
+                .map(|offset| start + 1 + offset)
+                .unwrap_or(lines.len());
+            let mut end = next_header;
+            while end > start + 1 && is_trivia(lines[end - 1]) {
+                end -= 1;
+            }
+            if end == next_header && next_header < lines.len() {
+                // Keep a blank line between our section and the next one
+                section.push(String::new());
+            }
+
+            output.extend(lines[..start].iter().map(|l| l.to_string()));
+            output.extend(section);
+            output.extend(lines[end..].iter().map(|l| l.to_string()));
+        }
+        None => {
+            output.extend(lines.iter().map(|l| l.to_string()));
+            if output.last().is_some_and(|l| !l.trim().is_empty()) {
+                output.push(String::new());
+            }
+            output.extend(section);
+        }
+    }
+
+    let mut result = output.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Replace `path` with `content` via a temporary file and rename.
+///
+/// An existing file keeps its permissions; a new file gets `new_file_mode` on Unix.
+pub fn write_atomic(path: &Path, content: &str, new_file_mode: u32) -> AppResult<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let permissions = fs::metadata(path).ok().map(|m| m.permissions());
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".awsr-tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(new_file_mode);
+    }
+    #[cfg(not(unix))]
+    let _ = new_file_mode;
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if let Some(permissions) = permissions {
+        fs::set_permissions(&tmp_path, permissions)?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[default]
+region = us-east-1
+
+# Comment line
+[profile dev]
+region = eu-west-1
+role_arn = arn:aws:iam::123456789012:role/DevRole
+s3 =
+  max_concurrent_requests = 20
+
+[sso-session corp]
+sso_region = us-east-1
+"#;
+
+    #[test]
+    fn test_parse_config_file() {
+        let profiles = parse_profiles(CONFIG, true);
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles["default"]["region"], "us-east-1");
+        assert_eq!(profiles["dev"]["region"], "eu-west-1");
+        assert_eq!(
+            profiles["dev"]["role_arn"],
+            "arn:aws:iam::123456789012:role/DevRole"
+        );
+        assert!(!profiles["dev"].contains_key("max_concurrent_requests"));
+        assert!(!profiles.contains_key("corp"));
+    }
+
+    #[test]
+    fn test_parse_credentials_file() {
+        let content = "[work]\naws_access_key_id = AKIAEXAMPLE\n; comment\n[profile x]\n";
+        let profiles = parse_profiles(content, false);
+        assert!(profiles.contains_key("work"));
+        assert!(profiles.contains_key("profile x"));
+        assert_eq!(profiles["work"]["aws_access_key_id"], "AKIAEXAMPLE");
+    }
+
+    fn export_config() -> Config {
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "dev".to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/DevRole".to_string(),
+            account_id: "123456789012".to_string(),
+            source_profile: Some("base".to_string()),
+            session_duration: Some(7200),
+            mfa_serial: Some("arn:aws:iam::123456789012:mfa/me".to_string()),
+            external_id: Some("ext-1".to_string()),
+            ..Default::default()
+        });
+        config
+    }
+
+    #[test]
+    fn test_render_assume_role_profiles() {
+        let config = export_config();
+        let rendered = render_profiles(&config, &config.roles, &ExportMode::AssumeRole);
+        assert_eq!(
+            rendered,
+            "[profile dev]\n\
+             role_arn = arn:aws:iam::123456789012:role/DevRole\n\
+             source_profile = base\n\
+             duration_seconds = 7200\n\
+             mfa_serial = arn:aws:iam::123456789012:mfa/me\n\
+             external_id = ext-1\n\n"
+        );
+
+        // The rendered sections parse back as the same profile
+        let parsed = parse_profiles(&rendered, true);
+        assert_eq!(parsed["dev"]["source_profile"], "base");
+    }
+
+    #[test]
+    fn test_render_chained_role_sources_parent_profile() {
+        let config = Config::new();
+        let prod = RoleConfig {
+            name: "prod".to_string(),
+            role_arn: "arn:aws:iam::210987654321:role/Prod".to_string(),
+            source_profile: Some("base".to_string()),
+            parent_role: Some("hub".to_string()),
+            ..Default::default()
+        };
+        let rendered = render_profiles(&config, &[prod], &ExportMode::AssumeRole);
+        assert!(rendered.contains("source_profile = hub\n"));
+    }
+
+    #[test]
+    fn test_render_sso_role_profile() {
+        let mut config = Config::new();
+        config.sso_start_url = Some("https://example.awsapps.com/start".to_string());
+        config.sso_region = Some("eu-west-1".to_string());
+        let admin = RoleConfig {
+            name: "admin".to_string(),
+            kind: RoleKind::Sso,
+            account_id: "123456789012".to_string(),
+            sso_role_name: Some("AdministratorAccess".to_string()),
+            ..Default::default()
+        };
+        let rendered = render_profiles(&config, &[admin], &ExportMode::AssumeRole);
+        assert_eq!(
+            rendered,
+            "[profile admin]\n\
+             sso_start_url = https://example.awsapps.com/start\n\
+             sso_region = eu-west-1\n\
+             sso_account_id = 123456789012\n\
+             sso_role_name = AdministratorAccess\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_credential_process_profiles() {
+        let config = export_config();
+        let mode = ExportMode::CredentialProcess {
+            command: "/opt/my tools/aws-assume-role".to_string(),
+        };
+        let rendered = render_profiles(&config, &config.roles, &mode);
+        assert!(rendered.contains(
+            "credential_process = \"/opt/my tools/aws-assume-role\" assume dev --format credential-process"
+        ));
+        assert!(!rendered.contains("role_arn"));
+        assert!(!rendered.contains("region"));
+
+        let mut config = config;
+        config.default_region = Some("eu-west-1".to_string());
+        let rendered = render_profiles(&config, &config.roles, &mode);
+        assert!(rendered.ends_with("region = eu-west-1\n\n"));
+    }
+
+    #[test]
+    fn test_govcloud_profile_round_trip() {
+        let profiles = parse_profiles(
+            "[profile gov]\n\
+             role_arn = arn:aws-us-gov:iam::111122223333:role/Ops\n\
+             region = us-gov-west-1\n\
+             use_fips_endpoint = true\n\
+             use_dualstack_endpoint = false\n",
+            true,
+        );
+        let role = role_from_profile("gov", &profiles["gov"]).unwrap().unwrap();
+        assert_eq!(role.account_id, "111122223333");
+        assert_eq!(role.use_fips_endpoint, Some(true));
+        assert_eq!(role.use_dualstack_endpoint, Some(false));
+
+        let mut config = Config::new();
+        config.roles.push(role);
+        let rendered = render_profiles(&config, &config.roles, &ExportMode::AssumeRole);
+        assert!(rendered.contains("role_arn = arn:aws-us-gov:iam::111122223333:role/Ops\n"));
+        assert!(rendered.contains(
+            "region = us-gov-west-1\nuse_fips_endpoint = true\nuse_dualstack_endpoint = false\n"
+        ));
+    }
+
+    #[test]
+    fn test_role_from_profile() {
+        let profiles = parse_profiles(
+            "[profile ops]\n\
+             role_arn = arn:aws:iam::111122223333:role/Ops\n\
+             source_profile = base\n\
+             duration_seconds = 7200\n\
+             mfa_serial = arn:aws:iam::111122223333:mfa/me\n\
+             external_id = ext\n\
+             role_session_name = me\n\
+             region = eu-central-1\n\
+             [profile plain]\n\
+             region = us-west-2\n\
+             [profile broken]\n\
+             role_arn = arn:aws:iam::111122223333:role/X\n\
+             duration_seconds = long\n\
+             [profile too-long]\n\
+             role_arn = arn:aws:iam::111122223333:role/X\n\
+             duration_seconds = 86400\n",
+            true,
+        );
+
+        let role = role_from_profile("ops", &profiles["ops"]).unwrap().unwrap();
+        assert_eq!(role.account_id, "111122223333");
+        assert_eq!(role.source_profile.as_deref(), Some("base"));
+        assert_eq!(role.session_duration, Some(7200));
+        assert_eq!(
+            role.mfa_serial.as_deref(),
+            Some("arn:aws:iam::111122223333:mfa/me")
+        );
+        assert_eq!(role.external_id.as_deref(), Some("ext"));
+        assert_eq!(role.session_name.as_deref(), Some("me"));
+        assert_eq!(role.region.as_deref(), Some("eu-central-1"));
+
+        assert!(role_from_profile("plain", &profiles["plain"])
+            .unwrap()
+            .is_none());
+        assert!(role_from_profile("broken", &profiles["broken"]).is_err());
+        assert!(role_from_profile("too-long", &profiles["too-long"]).is_err());
+
+        let mut bad_arn = ProfileProperties::new();
+        bad_arn.insert("role_arn".to_string(), "not-an-arn".to_string());
+        assert!(role_from_profile("bad", &bad_arn).is_err());
+    }
+
+    #[test]
+    fn test_merge_managed_block() {
+        let user = "[default]\nregion = us-east-1\n";
+        let merged = merge_managed_block(user, "[profile dev]\nrole_arn = a\n").unwrap();
+        assert_eq!(
+            merged,
+            format!(
+                "[default]\nregion = us-east-1\n\n{}\n[profile dev]\nrole_arn = a\n{}\n",
+                MANAGED_BLOCK_BEGIN, MANAGED_BLOCK_END
+            )
+        );
+
+        // Re-merging replaces the block and keeps user content on both sides
+        let with_tail = format!("{}[profile mine]\nregion = eu-west-1\n", merged);
+        let remerged = merge_managed_block(&with_tail, "[profile prod]\nrole_arn = b\n").unwrap();
+        assert!(remerged.starts_with("[default]\nregion = us-east-1\n\n"));
+        assert!(remerged.contains("[profile prod]"));
+        assert!(!remerged.contains("[profile dev]"));
+        assert!(remerged.ends_with("[profile mine]\nregion = eu-west-1\n"));
+        assert_eq!(remerged.matches(MANAGED_BLOCK_BEGIN).count(), 1);
+
+        assert_eq!(
+            user_owned_content(&with_tail).unwrap(),
+            "[default]\nregion = us-east-1\n\n[profile mine]\nregion = eu-west-1\n"
+        );
+    }
+
+    #[test]
+    fn test_managed_block_without_end_marker() {
+        let content = format!(
+            "[default]\nregion = us-east-1\n\n{}\n[profile dev]\nrole_arn = a\n\n[profile mine]\nregion = eu-west-1\n",
+            MANAGED_BLOCK_BEGIN
+        );
+
+        let err = merge_managed_block(&content, "[profile prod]\nrole_arn = b\n").unwrap_err();
+        assert!(err.to_string().contains("missing its end marker"));
+        assert!(user_owned_content(&content).is_err());
+    }
+
+    #[test]
+    fn test_upsert_section() {
+        let body = vec!["aws_access_key_id = NEW".to_string()];
+
+        let content = "# my creds\n[default]\naws_access_key_id = A\n\n[work]\n# expires soon\naws_access_key_id = OLD\n\n; next one\n[other]\nx = 1\n";
+        assert_eq!(
+            upsert_section(content, "work", &body),
+            "# my creds\n[default]\naws_access_key_id = A\n\n[work]\naws_access_key_id = NEW\n\n; next one\n[other]\nx = 1\n"
+        );
+
+        assert_eq!(
+            upsert_section("[default]\nx = 1", "work", &body),
+            "[default]\nx = 1\n\n[work]\naws_access_key_id = NEW\n"
+        );
+        assert_eq!(
+            upsert_section("", "work", &body),
+            "[work]\naws_access_key_id = NEW\n"
+        );
+
+        // Last section in the file, no trailing newline
+        assert_eq!(
+            upsert_section("[a]\nx = 1\n[work]\ny = 2", "work", &body),
+            "[a]\nx = 1\n[work]\naws_access_key_id = NEW\n"
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_preserves_permissions() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("aws").join("config");
+
+        write_atomic(&path, "first", 0o600).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            write_atomic(&path, "second", 0o600).unwrap();
+            assert_eq!(
+                fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+                0o640
+            );
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+    }
+
+    #[test]
+    fn test_profile_header_requires_separator() {
+        let profiles = parse_profiles("[profiledev]\nregion = x\n", true);
+        assert!(profiles.is_empty());
+    }
+}
@@ -0,0 +1,703 @@
+//! AWS IAM Identity Center (SSO) sign-in through the SSO-OIDC device
+//! authorization flow, and storage of the resulting access token.
+//!
+//! Tokens are stored like the AWS CLI v2 does (`<sha1(start_url)>.json`), so a
+//! token from `aws sso login` in `~/.aws/sso/cache` is picked up as well.
+
+use super::format_timestamp;
+use crate::cache::write_private;
+use crate::config::{config_dir, home_dir};
+use crate::error::{AppError, AppResult};
+use aws_config::SdkConfig;
+use aws_sdk_ssooidc::error::DisplayErrorContext;
+use aws_sdk_ssooidc::operation::create_token::{CreateTokenError, CreateTokenOutput};
+use aws_sdk_ssooidc::Client as OidcClient;
+use aws_sdk_sts::primitives::{DateTime, DateTimeFormat};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const CLIENT_NAME: &str = "aws-assume-role";
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const REFRESH_TOKEN_GRANT_TYPE: &str = "refresh_token";
+
+/// Access tokens are refreshed or replaced once they have less than this left.
+pub const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+
+/// An SSO access token, stored in the same shape as the AWS CLI v2 SSO cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoToken {
+    pub start_url: String,
+    pub region: String,
+    pub access_token: String,
+    /// ISO-8601 UTC, e.g. `2024-01-01T12:00:00Z`
+    pub expires_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// OIDC client registration, reused for later logins until it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_expires_at: Option<String>,
+}
+
+impl SsoToken {
+    pub fn expiration(&self) -> Option<SystemTime> {
+        parse_timestamp(&self.expires_at)
+    }
+
+    /// Whether the access token is still usable for at least `margin`.
+    pub fn is_valid(&self, margin: Duration) -> bool {
+        self.expiration()
+            .is_some_and(|expiration| expiration > SystemTime::now() + margin)
+    }
+
+    fn registration(&self) -> Option<ClientRegistration> {
+        let expires_at = parse_timestamp(self.registration_expires_at.as_deref()?)?;
+        if expires_at <= SystemTime::now() + Duration::from_secs(3600) {
+            return None;
+        }
+        Some(ClientRegistration {
+            client_id: self.client_id.clone()?,
+            client_secret: self.client_secret.clone()?,
+            expires_at,
+        })
+    }
+}
+
+/// The code the user confirms in the browser to approve a login.
+#[derive(Debug)]
+pub struct DeviceAuthorization {
+    pub verification_uri: String,
+    /// Verification URI with the user code already filled in
+    pub verification_uri_complete: Option<String>,
+    pub user_code: String,
+    pub expires_in: Duration,
+}
+
+struct ClientRegistration {
+    client_id: String,
+    client_secret: String,
+    expires_at: SystemTime,
+}
+
+/// Client for the SSO-OIDC device authorization flow.
+pub struct SsoLogin {
+    client: OidcClient,
+}
+
+impl SsoLogin {
+    /// Build a client for the OIDC endpoints of `region`.
+    pub async fn new(region: &str) -> Self {
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new(region.to_string()))
+            .no_credentials()
+            .load()
+            .await;
+        Self::new_with_config(&config)
+    }
+
+    pub fn new_with_config(config: &SdkConfig) -> Self {
+        Self {
+            client: OidcClient::new(config),
+        }
+    }
+
+    /// Run the device authorization flow for `start_url` and wait for the user
+    /// to approve it.
+    ///
+    /// `previous` is an earlier token whose client registration is reused while
+    /// valid. `prompt` is called once with the code to show the user.
+    pub async fn login(
+        &self,
+        start_url: &str,
+        region: &str,
+        previous: Option<&SsoToken>,
+        prompt: impl FnOnce(&DeviceAuthorization),
+    ) -> AppResult<SsoToken> {
+        let registration = match previous.and_then(SsoToken::registration) {
+            Some(registration) => registration,
+            None => self.register_client().await?,
+        };
+
+        let authorization = self
+            .client
+            .start_device_authorization()
+            .client_id(&registration.client_id)
+            .client_secret(&registration.client_secret)
+            .start_url(start_url)
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::AwsError(format!(
+                    "Failed to start SSO device authorization: {}",
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+        let device_code = authorization
+            .device_code()
+            .ok_or_else(|| AppError::AwsError("No device code returned by SSO-OIDC".to_string()))?;
+
+        prompt(&DeviceAuthorization {
+            verification_uri: authorization
+                .verification_uri()
+                .unwrap_or_default()
+                .to_string(),
+            verification_uri_complete: authorization
+                .verification_uri_complete()
+                .map(str::to_string),
+            user_code: authorization.user_code().unwrap_or_default().to_string(),
+            expires_in: Duration::from_secs(authorization.expires_in().max(0) as u64),
+        });
+
+        let deadline =
+            Instant::now() + Duration::from_secs(authorization.expires_in().max(0) as u64);
+        let mut interval = Duration::from_secs(match authorization.interval() {
+            interval if interval > 0 => interval as u64,
+            _ => 5,
+        });
+
+        let token = loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err(AppError::AwsError(
+                    "SSO login was not approved before the device code expired".to_string(),
+                ));
+            }
+
+            match self
+                .client
+                .create_token()
+                .client_id(&registration.client_id)
+                .client_secret(&registration.client_secret)
+                .grant_type(DEVICE_CODE_GRANT_TYPE)
+                .device_code(device_code)
+                .send()
+                .await
+            {
+                Ok(token) => break token,
+                Err(e) => match e.as_service_error() {
+                    Some(CreateTokenError::AuthorizationPendingException(_)) => {}
+                    Some(CreateTokenError::SlowDownException(_)) => {
+                        interval += Duration::from_secs(5)
+                    }
+                    _ => {
+                        return Err(AppError::AwsError(format!(
+                            "Failed to create SSO token: {}",
+                            DisplayErrorContext(&e)
+                        )))
+                    }
+                },
+            }
+        };
+
+        Ok(SsoToken {
+            start_url: start_url.to_string(),
+            region: region.to_string(),
+            access_token: access_token_from(&token)?,
+            expires_at: expires_at_from(&token),
+            refresh_token: token.refresh_token().map(str::to_string),
+            client_id: Some(registration.client_id),
+            client_secret: Some(registration.client_secret),
+            registration_expires_at: Some(format_timestamp(registration.expires_at)),
+        })
+    }
+
+    /// Exchange `token`'s refresh token for a new access token.
+    pub async fn refresh(&self, token: &SsoToken) -> AppResult<SsoToken> {
+        let (registration, refresh_token) =
+            match (token.registration(), token.refresh_token.as_deref()) {
+                (Some(registration), Some(refresh_token)) => (registration, refresh_token),
+                _ => {
+                    return Err(AppError::AwsError(format!(
+                        "The SSO token for {} cannot be refreshed",
+                        token.start_url
+                    )))
+                }
+            };
+
+        let output = self
+            .client
+            .create_token()
+            .client_id(&registration.client_id)
+            .client_secret(&registration.client_secret)
+            .grant_type(REFRESH_TOKEN_GRANT_TYPE)
+            .refresh_token(refresh_token)
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::AwsError(format!(
+                    "Failed to refresh SSO token: {}",
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+
+        Ok(SsoToken {
+            access_token: access_token_from(&output)?,
+            expires_at: expires_at_from(&output),
+            refresh_token: output
+                .refresh_token()
+                .map(str::to_string)
+                .or_else(|| token.refresh_token.clone()),
+            ..token.clone()
+        })
+    }
+
+    async fn register_client(&self) -> AppResult<ClientRegistration> {
+        let output = self
+            .client
+            .register_client()
+            .client_name(CLIENT_NAME)
+            .client_type("public")
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::AwsError(format!(
+                    "Failed to register SSO-OIDC client: {}",
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+
+        match (output.client_id(), output.client_secret()) {
+            (Some(client_id), Some(client_secret)) => Ok(ClientRegistration {
+                client_id: client_id.to_string(),
+                client_secret: client_secret.to_string(),
+                expires_at: UNIX_EPOCH
+                    + Duration::from_secs(output.client_secret_expires_at().max(0) as u64),
+            }),
+            _ => Err(AppError::AwsError(
+                "No client credentials returned by SSO-OIDC".to_string(),
+            )),
+        }
+    }
+}
+
+/// A usable access token for `start_url`, from the first of `stores` holding a
+/// valid one. Otherwise an expired token with a refresh token is refreshed
+/// through `login` and written back where it was found.
+///
+/// `None` means a new `awsr sso login` (or `aws sso login`) is needed.
+pub async fn cached_token(
+    stores: &[SsoTokenStore],
+    start_url: &str,
+    login: &SsoLogin,
+) -> Option<SsoToken> {
+    let tokens: Vec<(&SsoTokenStore, SsoToken)> = stores
+        .iter()
+        .filter_map(|store| store.get(start_url).map(|token| (store, token)))
+        .collect();
+
+    if let Some((_, token)) = tokens
+        .iter()
+        .find(|(_, token)| token.is_valid(TOKEN_EXPIRY_MARGIN))
+    {
+        return Some(token.clone());
+    }
+
+    for (store, token) in &tokens {
+        if token.refresh_token.is_none() {
+            continue;
+        }
+        match login.refresh(token).await {
+            Ok(refreshed) => {
+                if let Err(e) = store.put(&refreshed) {
+                    eprintln!("⚠️  Could not store refreshed SSO token: {}", e);
+                }
+                return Some(refreshed);
+            }
+            Err(e) => eprintln!("⚠️  {}", e),
+        }
+    }
+
+    None
+}
+
+/// On-disk store of SSO access tokens, one file per start URL.
+pub struct SsoTokenStore {
+    dir: PathBuf,
+}
+
+impl SsoTokenStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The store under awsr's config directory (`~/.aws-assume-role/sso`).
+    pub fn open() -> AppResult<Self> {
+        Ok(Self::new(config_dir()?.join("sso")))
+    }
+
+    /// The AWS CLI v2 cache (`~/.aws/sso/cache`).
+    pub fn aws_cli() -> AppResult<Self> {
+        Ok(Self::new(
+            home_dir()?.join(".aws").join("sso").join("cache"),
+        ))
+    }
+
+    /// awsr's own store followed by the AWS CLI v2 cache.
+    pub fn all() -> AppResult<Vec<Self>> {
+        Ok(vec![Self::open()?, Self::aws_cli()?])
+    }
+
+    /// The stored token for `start_url`, valid or not. Unreadable files and
+    /// tokens for another start URL are treated as missing.
+    ///
+    /// AWS CLI v2 profiles that use an `[sso-session]` section cache the token
+    /// under `sha1(session_name)` instead, so when the start URL's own file has
+    /// no unexpired token, the directory is searched for one.
+    pub fn get(&self, start_url: &str) -> Option<SsoToken> {
+        let token =
+            read_token(&self.token_path(start_url)).filter(|token| token.start_url == start_url);
+        if token
+            .as_ref()
+            .is_some_and(|token| token.is_valid(Duration::ZERO))
+        {
+            return token;
+        }
+        self.find_unexpired(start_url).or(token)
+    }
+
+    /// The unexpired token for `start_url` that lasts longest, in any file.
+    fn find_unexpired(&self, start_url: &str) -> Option<SsoToken> {
+        fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .filter_map(|path| read_token(&path))
+            .filter(|token| token.start_url == start_url && token.is_valid(Duration::ZERO))
+            .max_by_key(SsoToken::expiration)
+    }
+
+    /// Store `token`, replacing any previous token for its start URL.
+    pub fn put(&self, token: &SsoToken) -> AppResult<PathBuf> {
+        fs::create_dir_all(&self.dir).map_err(|e| {
+            AppError::ConfigError(format!("Failed to create SSO token directory: {}", e))
+        })?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))?;
+        }
+
+        let content = serde_json::to_string_pretty(token)
+            .map_err(|e| AppError::ConfigError(format!("Failed to serialize SSO token: {}", e)))?;
+        let path = self.token_path(&token.start_url);
+        write_private(&path, content.as_bytes())?;
+        Ok(path)
+    }
+
+    /// `<sha1(start_url)>.json`, the AWS CLI v2 cache file name.
+    pub fn token_path(&self, start_url: &str) -> PathBuf {
+        let digest = Sha1::digest(start_url.as_bytes());
+        let file_name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.json", file_name))
+    }
+}
+
+fn read_token(path: &Path) -> Option<SsoToken> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn access_token_from(output: &CreateTokenOutput) -> AppResult<String> {
+    output
+        .access_token()
+        .map(str::to_string)
+        .ok_or_else(|| AppError::AwsError("No access token returned by SSO-OIDC".to_string()))
+}
+
+fn expires_at_from(output: &CreateTokenOutput) -> String {
+    format_timestamp(SystemTime::now() + Duration::from_secs(output.expires_in().max(0) as u64))
+}
+
+/// Parse an `expiresAt` value. Older AWS CLI versions wrote a `UTC` suffix
+/// instead of `Z`.
+fn parse_timestamp(value: &str) -> Option<SystemTime> {
+    let value = match value.strip_suffix("UTC") {
+        Some(stripped) => format!("{}Z", stripped),
+        None => value.to_string(),
+    };
+    DateTime::from_str(&value, DateTimeFormat::DateTime)
+        .ok()
+        .and_then(|time| SystemTime::try_from(time).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const START_URL: &str = "https://example.awsapps.com/start";
+
+    fn token(expires_at: SystemTime) -> SsoToken {
+        SsoToken {
+            start_url: START_URL.to_string(),
+            region: "us-east-1".to_string(),
+            access_token: "token".to_string(),
+            expires_at: format_timestamp(expires_at),
+            refresh_token: None,
+            client_id: None,
+            client_secret: None,
+            registration_expires_at: None,
+        }
+    }
+
+    fn oidc_error(error_type: &str) -> ResponseTemplate {
+        ResponseTemplate::new(400)
+            .insert_header("x-amzn-ErrorType", error_type)
+            .set_body_json(serde_json::json!({ "error": "pending" }))
+    }
+
+    async fn stub_login(server: &MockServer) -> SsoLogin {
+        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_config::Region::new("us-east-1"))
+            .endpoint_url(server.uri())
+            .no_credentials()
+            .load()
+            .await;
+        SsoLogin::new_with_config(&config)
+    }
+
+    #[tokio::test]
+    async fn test_device_authorization_flow() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/client/register"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "clientId": "client-id",
+                "clientSecret": "client-secret",
+                "clientIdIssuedAt": 1_700_000_000,
+                "clientSecretExpiresAt": 4_000_000_000_i64
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/device_authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "deviceCode": "device-code",
+                "userCode": "ABCD-EFGH",
+                "verificationUri": "https://device.sso.example/",
+                "verificationUriComplete": "https://device.sso.example/?user_code=ABCD-EFGH",
+                "expiresIn": 60,
+                "interval": 1
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(oidc_error("AuthorizationPendingException"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "accessToken": "access-token",
+                "tokenType": "Bearer",
+                "expiresIn": 28800,
+                "refreshToken": "refresh-token"
+            })))
+            .mount(&server)
+            .await;
+
+        let mut shown_code = None;
+        let token = stub_login(&server)
+            .await
+            .login(START_URL, "us-east-1", None, |authorization| {
+                shown_code = Some(authorization.user_code.clone())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(shown_code.as_deref(), Some("ABCD-EFGH"));
+        assert_eq!(token.access_token, "access-token");
+        assert_eq!(token.refresh_token.as_deref(), Some("refresh-token"));
+        assert_eq!(token.client_id.as_deref(), Some("client-id"));
+        assert!(token.is_valid(Duration::from_secs(3600)));
+        // The registration is reusable, so a second login skips RegisterClient
+        assert!(token.registration().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_denied_authorization_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/device_authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "deviceCode": "device-code",
+                "userCode": "ABCD-EFGH",
+                "verificationUri": "https://device.sso.example/",
+                "expiresIn": 60,
+                "interval": 1
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(oidc_error("AccessDeniedException"))
+            .mount(&server)
+            .await;
+
+        let mut previous = token(SystemTime::now());
+        previous.client_id = Some("client-id".to_string());
+        previous.client_secret = Some("client-secret".to_string());
+        previous.registration_expires_at = Some(format_timestamp(
+            SystemTime::now() + Duration::from_secs(86400),
+        ));
+
+        let err = stub_login(&server)
+            .await
+            .login(START_URL, "us-east-1", Some(&previous), |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("AccessDeniedException"));
+    }
+
+    #[test]
+    fn test_token_store_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SsoTokenStore::new(temp_dir.path().join("sso"));
+        assert!(store.get(START_URL).is_none());
+
+        let token = token(SystemTime::now() + Duration::from_secs(3600));
+        let path = store.put(&token).unwrap();
+        // sha1("https://example.awsapps.com/start"), as named by the AWS CLI
+        assert_eq!(
+            path.file_name().unwrap(),
+            "e8be5486177c5b5392bd9aa76563515b29358e6e.json"
+        );
+        assert_eq!(store.get(START_URL), Some(token.clone()));
+
+        // Same field names as the AWS CLI v2 cache
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\"startUrl\""));
+        assert!(content.contains("\"accessToken\""));
+        assert!(content.contains("\"expiresAt\""));
+    }
+
+    #[tokio::test]
+    async fn test_cached_token_refreshes_expired_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_partial_json(serde_json::json!({
+                "grantType": "refresh_token",
+                "refreshToken": "refresh-token"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "accessToken": "refreshed",
+                "expiresIn": 3600
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let awsr_store = SsoTokenStore::new(temp_dir.path().join("awsr"));
+        let cli_store = SsoTokenStore::new(temp_dir.path().join("cli"));
+        let stores = [awsr_store, cli_store];
+        let login = stub_login(&server).await;
+
+        assert!(cached_token(&stores, START_URL, &login).await.is_none());
+
+        // An expired AWS CLI token with a refresh token and live registration
+        let mut expired = token(SystemTime::now() - Duration::from_secs(60));
+        expired.refresh_token = Some("refresh-token".to_string());
+        expired.client_id = Some("client-id".to_string());
+        expired.client_secret = Some("client-secret".to_string());
+        expired.registration_expires_at = Some(format_timestamp(
+            SystemTime::now() + Duration::from_secs(86400),
+        ));
+        stores[1].put(&expired).unwrap();
+
+        let refreshed = cached_token(&stores, START_URL, &login).await.unwrap();
+        assert_eq!(refreshed.access_token, "refreshed");
+        assert_eq!(refreshed.refresh_token.as_deref(), Some("refresh-token"));
+
+        // Written back to the cache it came from, so the next lookup is local
+        assert_eq!(stores[1].get(START_URL), Some(refreshed.clone()));
+        assert!(stores[0].get(START_URL).is_none());
+        assert_eq!(
+            cached_token(&stores, START_URL, &login).await,
+            Some(refreshed)
+        );
+    }
+
+    #[test]
+    fn test_aws_cli_token_format() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SsoTokenStore::new(temp_dir.path().to_path_buf());
+        fs::write(
+            store.token_path(START_URL),
+            r#"{"startUrl": "https://example.awsapps.com/start", "region": "us-east-1", "accessToken": "abc", "expiresAt": "2099-01-01T00:00:00UTC"}"#,
+        )
+        .unwrap();
+
+        let token = store.get(START_URL).unwrap();
+        assert_eq!(token.access_token, "abc");
+        assert!(token.is_valid(TOKEN_EXPIRY_MARGIN));
+
+        // A file whose startUrl does not match is ignored
+        fs::write(
+            store.token_path("https://other.awsapps.com/start"),
+            fs::read_to_string(store.token_path(START_URL)).unwrap(),
+        )
+        .unwrap();
+        assert!(store.get("https://other.awsapps.com/start").is_none());
+    }
+
+    #[test]
+    fn test_sso_session_token_lookup() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SsoTokenStore::new(temp_dir.path().to_path_buf());
+        let write = |file_name: &str, token: &SsoToken| {
+            fs::write(
+                temp_dir.path().join(file_name),
+                serde_json::to_string(token).unwrap(),
+            )
+            .unwrap();
+        };
+
+        // `aws configure sso` names the file after sha1 of the sso-session name
+        let expired = token(SystemTime::now() - Duration::from_secs(60));
+        write("7a9d8f5c1e2b.json", &expired);
+        assert_eq!(store.get(START_URL), None);
+
+        let valid = token(SystemTime::now() + Duration::from_secs(3600));
+        write("0b1c2d3e4f5a.json", &valid);
+        // Client registrations share the directory and are skipped
+        fs::write(
+            temp_dir.path().join("botocore-client-id-us-east-1.json"),
+            r#"{"clientId": "id", "clientSecret": "secret", "expiresAt": "2099-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(store.get(START_URL), Some(valid.clone()));
+        assert!(store.get("https://other.awsapps.com/start").is_none());
+
+        // An expired token in the start URL's own file does not hide it
+        store.put(&expired).unwrap();
+        assert_eq!(store.get(START_URL), Some(valid));
+    }
+
+    #[test]
+    fn test_token_validity() {
+        assert!(token(SystemTime::now() + Duration::from_secs(3600)).is_valid(Duration::ZERO));
+        assert!(!token(SystemTime::now() - Duration::from_secs(1)).is_valid(Duration::ZERO));
+
+        let mut unparsable = token(SystemTime::now());
+        unparsable.expires_at = "soon".to_string();
+        assert!(!unparsable.is_valid(Duration::ZERO));
+    }
+}
@@ -0,0 +1,624 @@
+//! On-disk cache of assumed role credentials.
+//!
+//! Each role gets one JSON file under `~/.aws-assume-role/cache/`. An entry is
+//! only reused when the parameters it was issued for match the current request
+//! exactly and it is not about to expire.
+
+use crate::aws::{resolve_session_policy, Credentials};
+use crate::config::{config_dir, Config, RoleConfig, RoleKind, SessionOverrides};
+use crate::error::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reuse cached credentials only while they have at least this long left.
+pub const DEFAULT_EXPIRY_MARGIN_SECONDS: u64 = 300;
+
+/// Everything that influences the credentials STS hands out for a role.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub role_name: String,
+    pub role_arn: String,
+    pub source_profile: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub external_id: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub session_name: Option<String>,
+    /// Intermediate roles of a role chain, root first
+    #[serde(default)]
+    pub parent_hops: Vec<ParentHopKey>,
+    /// `account_id/sso_role_name` of an SSO role
+    #[serde(default)]
+    pub sso_role: Option<String>,
+    /// IAM Identity Center instance of a chain that starts from an SSO role
+    #[serde(default)]
+    pub sso_start_url: Option<String>,
+    #[serde(default)]
+    pub sso_region: Option<String>,
+    /// Custom STS/SSO endpoint, so emulator credentials never stand in for real ones
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Session tag templates, configured and from `--tag`
+    #[serde(default)]
+    pub tags: Vec<(String, String)>,
+    #[serde(default)]
+    pub transitive_tag_keys: Vec<String>,
+    /// Minified inline session policy, or its `@path` source if unreadable
+    #[serde(default)]
+    pub session_policy: Option<String>,
+    #[serde(default)]
+    pub policy_arns: Vec<String>,
+    /// SourceIdentity template
+    #[serde(default)]
+    pub source_identity: Option<String>,
+}
+
+/// The settings an intermediate role of a chain is assumed with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParentHopKey {
+    pub role_name: String,
+    pub role_arn: String,
+    pub source_profile: Option<String>,
+    pub external_id: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub session_name: Option<String>,
+    pub source_identity: Option<String>,
+    pub endpoint_url: Option<String>,
+    pub tags: Vec<(String, String)>,
+    pub transitive_tag_keys: Vec<String>,
+    pub session_policy: Option<String>,
+    pub policy_arns: Vec<String>,
+}
+
+impl ParentHopKey {
+    fn new(config: &Config, role: &RoleConfig, session_name: Option<&str>) -> Self {
+        Self {
+            role_name: role.name.clone(),
+            role_arn: role.role_arn.clone(),
+            source_profile: config.source_profile_for(role).map(str::to_string),
+            external_id: role.external_id.clone(),
+            mfa_serial: role.mfa_serial.clone(),
+            session_name: session_name
+                .or(config.session_name_for(role))
+                .map(str::to_string),
+            source_identity: config.source_identity_for(role).map(str::to_string),
+            endpoint_url: config.endpoint_url_for(Some(role)),
+            tags: role.session_tags(&[]),
+            transitive_tag_keys: role.transitive_tag_keys.clone(),
+            session_policy: role.session_policy.as_deref().map(|source| {
+                resolve_session_policy(source).unwrap_or_else(|_| source.to_string())
+            }),
+            policy_arns: role.policy_arns.clone(),
+        }
+    }
+}
+
+impl CacheKey {
+    /// Build the key for assuming `role` with the given CLI overrides.
+    ///
+    /// The session name and tags are keyed by their templates rather than the
+    /// rendered values, which may contain a timestamp. A session policy file is
+    /// keyed by its content, so editing it issues a new session.
+    pub fn new(
+        config: &Config,
+        role: &RoleConfig,
+        duration_seconds: Option<i32>,
+        overrides: &SessionOverrides,
+    ) -> Self {
+        // An invalid chain fails before anything is cached; key it as a plain role
+        let chain = config.role_chain(role).unwrap_or_else(|_| vec![role]);
+        let parents = &chain[..chain.len() - 1];
+        let from_sso = chain.iter().any(|r| r.kind == RoleKind::Sso);
+        Self {
+            role_name: role.name.clone(),
+            role_arn: role.role_arn.clone(),
+            source_profile: config.source_profile_for(chain[0]).map(str::to_string),
+            duration_seconds,
+            external_id: role.external_id.clone(),
+            mfa_serial: role.mfa_serial.clone(),
+            session_name: overrides
+                .session_name
+                .or(config.session_name_for(role))
+                .map(str::to_string),
+            parent_hops: parents
+                .iter()
+                .map(|parent| ParentHopKey::new(config, parent, overrides.session_name))
+                .collect(),
+            sso_role: match role.kind {
+                RoleKind::Sso => Some(format!(
+                    "{}/{}",
+                    role.account_id,
+                    role.sso_role_name.as_deref().unwrap_or_default()
+                )),
+                RoleKind::Sts => None,
+            },
+            sso_start_url: config.sso_start_url.clone().filter(|_| from_sso),
+            sso_region: config.sso_region.clone().filter(|_| from_sso),
+            endpoint_url: config.endpoint_url_for(Some(role)),
+            tags: role.session_tags(overrides.tags),
+            transitive_tag_keys: role.transitive_tag_keys.clone(),
+            session_policy: role.session_policy_for(overrides).map(|source| {
+                resolve_session_policy(source).unwrap_or_else(|_| source.to_string())
+            }),
+            policy_arns: role.policy_arns_for(overrides).to_vec(),
+            source_identity: config.source_identity_for(role).map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: CacheKey,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    /// ARN of the assumed role session
+    #[serde(default)]
+    pub assumed_role_arn: Option<String>,
+    /// SourceIdentity of the session
+    #[serde(default)]
+    pub source_identity: Option<String>,
+    /// Unix time the credentials were issued
+    pub issued_at: u64,
+    /// Unix time the credentials expire
+    pub expiration: u64,
+}
+
+impl CacheEntry {
+    fn new(key: CacheKey, credentials: &Credentials, expiration: SystemTime) -> Self {
+        Self {
+            key,
+            access_key_id: credentials.access_key_id.clone(),
+            secret_access_key: credentials.secret_access_key.clone(),
+            session_token: credentials.session_token.clone(),
+            assumed_role_arn: credentials.assumed_role_arn.clone(),
+            source_identity: credentials.source_identity.clone(),
+            issued_at: unix_seconds(SystemTime::now()),
+            expiration: unix_seconds(expiration),
+        }
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        Credentials {
+            access_key_id: self.access_key_id.clone(),
+            secret_access_key: self.secret_access_key.clone(),
+            session_token: self.session_token.clone(),
+            expiration: Some(UNIX_EPOCH + Duration::from_secs(self.expiration)),
+            assumed_role_arn: self.assumed_role_arn.clone(),
+            source_identity: self.source_identity.clone(),
+        }
+    }
+
+    /// Time left before the credentials expire, `None` once they have.
+    pub fn remaining(&self) -> Option<Duration> {
+        (UNIX_EPOCH + Duration::from_secs(self.expiration))
+            .duration_since(SystemTime::now())
+            .ok()
+    }
+}
+
+pub struct CredentialCache {
+    dir: PathBuf,
+}
+
+impl CredentialCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The cache under the awsr config directory.
+    pub fn open() -> AppResult<Self> {
+        Ok(Self::new(config_dir()?.join("cache")))
+    }
+
+    /// Cached credentials for `key` with more than `margin` left, if any.
+    ///
+    /// Unreadable or stale entries are treated as a miss.
+    pub fn get(&self, key: &CacheKey, margin: Duration) -> Option<Credentials> {
+        let content = fs::read_to_string(self.entry_path(&key.role_name)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&content).ok()?;
+
+        if entry.key != *key {
+            return None;
+        }
+        match entry.remaining() {
+            Some(remaining) if remaining > margin => Some(entry.credentials()),
+            _ => None,
+        }
+    }
+
+    /// Store credentials for `key`, replacing any previous entry for the role.
+    ///
+    /// Credentials without an expiration are not cached.
+    pub fn put(&self, key: CacheKey, credentials: &Credentials) -> AppResult<()> {
+        let expiration = match credentials.expiration {
+            Some(expiration) => expiration,
+            None => return Ok(()),
+        };
+
+        let path = self.entry_path(&key.role_name);
+        let entry = CacheEntry::new(key, credentials, expiration);
+        let content = serde_json::to_string_pretty(&entry).map_err(|e| {
+            AppError::ConfigError(format!("Failed to serialize cache entry: {}", e))
+        })?;
+
+        self.ensure_dir()?;
+        write_private(&path, content.as_bytes())
+    }
+
+    /// All readable cache entries, sorted by role name.
+    pub fn entries(&self) -> AppResult<Vec<CacheEntry>> {
+        let mut entries: Vec<CacheEntry> = self
+            .entry_files()?
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        entries.sort_by(|a, b| a.key.role_name.cmp(&b.key.role_name));
+        Ok(entries)
+    }
+
+    /// Remove the entry for `role_name`. Returns whether one existed.
+    pub fn remove(&self, role_name: &str) -> AppResult<bool> {
+        let path = self.entry_path(role_name);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(path)?;
+        Ok(true)
+    }
+
+    /// Remove every entry. Returns how many were removed.
+    pub fn clear(&self) -> AppResult<usize> {
+        let files = self.entry_files()?;
+        for path in &files {
+            fs::remove_file(path)?;
+        }
+        Ok(files.len())
+    }
+
+    /// Remove expired and unreadable entries. Returns how many were removed.
+    pub fn prune(&self) -> AppResult<usize> {
+        let mut removed = 0;
+        for path in self.entry_files()? {
+            let live = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<CacheEntry>(&content).ok())
+                .is_some_and(|entry| entry.remaining().is_some());
+            if !live {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entry_files(&self) -> AppResult<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Percent-encode everything but ASCII alphanumerics, `-` and `_`, so
+    /// distinct role names never share a file and none can escape the directory.
+    fn entry_path(&self, role_name: &str) -> PathBuf {
+        let file_name: String = role_name
+            .bytes()
+            .map(|b| {
+                if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                    char::from(b).to_string()
+                } else {
+                    format!("%{:02X}", b)
+                }
+            })
+            .collect();
+        self.dir.join(format!("{}.json", file_name))
+    }
+
+    fn ensure_dir(&self) -> AppResult<()> {
+        fs::create_dir_all(&self.dir).map_err(|e| {
+            AppError::ConfigError(format!("Failed to create cache directory: {}", e))
+        })?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.dir, fs::Permissions::from_mode(0o700))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Atomically write `content` to `path`, readable only by the current user.
+pub(crate) fn write_private(path: &PathBuf, content: &[u8]) -> AppResult<()> {
+    let tmp_path = path.with_extension("json.tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn key(role_name: &str) -> CacheKey {
+        CacheKey {
+            role_name: role_name.to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/DevRole".to_string(),
+            source_profile: None,
+            duration_seconds: None,
+            external_id: None,
+            mfa_serial: None,
+            session_name: None,
+            parent_hops: Vec::new(),
+            sso_role: None,
+            sso_start_url: None,
+            sso_region: None,
+            endpoint_url: None,
+            tags: Vec::new(),
+            transitive_tag_keys: Vec::new(),
+            session_policy: None,
+            policy_arns: Vec::new(),
+            source_identity: None,
+        }
+    }
+
+    fn credentials(valid_for: Duration) -> Credentials {
+        Credentials {
+            access_key_id: "ASIAEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expiration: Some(SystemTime::now() + valid_for),
+            assumed_role_arn: Some(
+                "arn:aws:sts::123456789012:assumed-role/DevRole/session".to_string(),
+            ),
+            source_identity: None,
+        }
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().join("cache"));
+
+        cache
+            .put(key("dev"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let cached = cache.get(&key("dev"), Duration::from_secs(300)).unwrap();
+        assert_eq!(cached.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(cached.session_token.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn test_expiry_margin() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().to_path_buf());
+
+        cache
+            .put(key("dev"), &credentials(Duration::from_secs(120)))
+            .unwrap();
+
+        assert!(cache.get(&key("dev"), Duration::from_secs(300)).is_none());
+        assert!(cache.get(&key("dev"), Duration::from_secs(60)).is_some());
+    }
+
+    #[test]
+    fn test_key_mismatch_is_a_miss() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().to_path_buf());
+
+        cache
+            .put(key("dev"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let mut other = key("dev");
+        other.duration_seconds = Some(7200);
+        assert!(cache.get(&other, Duration::ZERO).is_none());
+        assert!(cache.get(&key("prod"), Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_chained_key_uses_root_source_profile() {
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "hub".to_string(),
+            source_profile: Some("corp".to_string()),
+            ..Default::default()
+        });
+        let prod = RoleConfig {
+            name: "prod".to_string(),
+            parent_role: Some("hub".to_string()),
+            ..Default::default()
+        };
+
+        let key = CacheKey::new(&config, &prod, None, &SessionOverrides::default());
+        assert_eq!(key.source_profile.as_deref(), Some("corp"));
+        assert_eq!(key.parent_hops.len(), 1);
+        assert_eq!(key.parent_hops[0].role_name, "hub");
+    }
+
+    #[test]
+    fn test_changed_parent_role_is_a_miss() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().join("cache"));
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "hub".to_string(),
+            role_arn: "arn:aws:iam::111111111111:role/Hub".to_string(),
+            ..Default::default()
+        });
+        let prod = RoleConfig {
+            name: "prod".to_string(),
+            role_arn: "arn:aws:iam::222222222222:role/Prod".to_string(),
+            parent_role: Some("hub".to_string()),
+            ..Default::default()
+        };
+        let overrides = SessionOverrides::default();
+        let key = CacheKey::new(&config, &prod, None, &overrides);
+        cache
+            .put(key.clone(), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+        assert!(cache.get(&key, Duration::ZERO).is_some());
+
+        config.add_role(RoleConfig {
+            name: "hub".to_string(),
+            role_arn: "arn:aws:iam::333333333333:role/Hub".to_string(),
+            ..Default::default()
+        });
+        let changed = CacheKey::new(&config, &prod, None, &overrides);
+        assert!(cache.get(&changed, Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_sso_key_includes_instance() {
+        let mut config = Config::new();
+        config.sso_start_url = Some("https://one.awsapps.com/start".to_string());
+        config.sso_region = Some("us-east-1".to_string());
+        let role = RoleConfig {
+            name: "sso-dev".to_string(),
+            kind: RoleKind::Sso,
+            account_id: "123456789012".to_string(),
+            sso_role_name: Some("Developer".to_string()),
+            ..Default::default()
+        };
+        let overrides = SessionOverrides::default();
+        let key = CacheKey::new(&config, &role, None, &overrides);
+        assert_eq!(
+            key.sso_start_url.as_deref(),
+            Some("https://one.awsapps.com/start")
+        );
+
+        config.sso_start_url = Some("https://two.awsapps.com/start".to_string());
+        assert_ne!(CacheKey::new(&config, &role, None, &overrides), key);
+        assert_eq!(
+            CacheKey::new(&config, &RoleConfig::default(), None, &overrides).sso_region,
+            None
+        );
+    }
+
+    #[test]
+    fn test_corrupt_entry_is_a_miss() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().to_path_buf());
+        fs::write(temp_dir.path().join("dev.json"), "{ not json").unwrap();
+
+        assert!(cache.get(&key("dev"), Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_list_remove_and_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().to_path_buf());
+        assert!(cache.entries().unwrap().is_empty());
+
+        cache
+            .put(key("prod"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+        cache
+            .put(key("dev"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+        let mut expired = credentials(Duration::ZERO);
+        expired.expiration = Some(SystemTime::now() - Duration::from_secs(60));
+        cache.put(key("old"), &expired).unwrap();
+        fs::write(temp_dir.path().join("broken.json"), "nope").unwrap();
+
+        let names: Vec<String> = cache
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.key.role_name)
+            .collect();
+        assert_eq!(names, vec!["dev", "old", "prod"]);
+
+        assert_eq!(cache.prune().unwrap(), 2);
+        assert_eq!(cache.entries().unwrap().len(), 2);
+
+        assert!(cache.remove("dev").unwrap());
+        assert!(!cache.remove("dev").unwrap());
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_entry_path_is_sanitized() {
+        let cache = CredentialCache::new(PathBuf::from("/cache"));
+        assert_eq!(cache.entry_path("dev"), PathBuf::from("/cache/dev.json"));
+        assert_eq!(
+            cache.entry_path("../team/dev"),
+            PathBuf::from("/cache/%2E%2E%2Fteam%2Fdev.json")
+        );
+        assert_ne!(cache.entry_path("a/b"), cache.entry_path("a_b"));
+        assert_ne!(cache.entry_path("a/b"), cache.entry_path("a%2Fb"));
+    }
+
+    #[test]
+    fn test_similar_role_names_do_not_collide() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().join("cache"));
+        cache
+            .put(key("a/b"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+        cache
+            .put(key("a_b"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let margin = Duration::from_secs(300);
+        assert!(cache.get(&key("a/b"), margin).is_some());
+        assert!(cache.get(&key("a_b"), margin).is_some());
+        assert_eq!(cache.entries().unwrap().len(), 2);
+
+        assert!(cache.remove("a/b").unwrap());
+        assert!(cache.get(&key("a_b"), margin).is_some());
+        assert_eq!(cache.clear().unwrap(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let cache = CredentialCache::new(temp_dir.path().join("cache"));
+        cache
+            .put(key("dev"), &credentials(Duration::from_secs(3600)))
+            .unwrap();
+
+        let mode = fs::metadata(temp_dir.path().join("cache").join("dev.json"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
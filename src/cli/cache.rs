@@ -0,0 +1,175 @@
+use crate::aws::format_timestamp;
+use crate::cache::{CacheEntry, CredentialCache};
+use crate::error::{AppError, AppResult};
+use clap::Subcommand;
+use serde::Serialize;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Show cached sessions and their remaining lifetime
+    List {
+        /// Output format
+        #[arg(
+            short,
+            long,
+            default_value = "table",
+            help = "Output format: 'table' or 'json'"
+        )]
+        format: String,
+    },
+
+    /// Remove cached credentials for one role, or all roles
+    Clear {
+        /// Role to evict (all roles when omitted)
+        #[arg(help = "Role name from 'awsr cache list'")]
+        role: Option<String>,
+    },
+
+    /// Remove expired cache entries
+    Prune,
+}
+
+/// A cache entry as shown to the user. Never includes secret values.
+#[derive(Serialize)]
+struct CacheListing {
+    role: String,
+    role_arn: String,
+    identity_arn: Option<String>,
+    issued_at: String,
+    expiration: String,
+    remaining_seconds: u64,
+    expired: bool,
+    /// Whether session policies narrow the cached session
+    session_policy: bool,
+    policy_arns: Vec<String>,
+}
+
+impl From<&CacheEntry> for CacheListing {
+    fn from(entry: &CacheEntry) -> Self {
+        let remaining = entry.remaining();
+        Self {
+            role: entry.key.role_name.clone(),
+            role_arn: entry.key.role_arn.clone(),
+            identity_arn: entry.assumed_role_arn.clone(),
+            issued_at: format_timestamp(UNIX_EPOCH + Duration::from_secs(entry.issued_at)),
+            expiration: format_timestamp(UNIX_EPOCH + Duration::from_secs(entry.expiration)),
+            remaining_seconds: remaining.map(|d| d.as_secs()).unwrap_or_default(),
+            expired: remaining.is_none(),
+            session_policy: entry.key.session_policy.is_some(),
+            policy_arns: entry.key.policy_arns.clone(),
+        }
+    }
+}
+
+pub fn run(command: &CacheCommands) -> AppResult<()> {
+    let cache = CredentialCache::open()?;
+
+    match command {
+        CacheCommands::List { format } => {
+            let listings: Vec<CacheListing> =
+                cache.entries()?.iter().map(CacheListing::from).collect();
+
+            match format.as_str() {
+                "json" => {
+                    let json = serde_json::to_string_pretty(&listings).map_err(|e| {
+                        AppError::CliError(format!("Failed to serialize cache listing: {}", e))
+                    })?;
+                    println!("{}", json);
+                }
+                "table" => print_table(&listings),
+                other => {
+                    return Err(AppError::CliError(format!(
+                        "Unknown format '{}': expected 'table' or 'json'",
+                        other
+                    )))
+                }
+            }
+        }
+
+        CacheCommands::Clear { role: Some(role) } => {
+            if cache.remove(role)? {
+                println!("Cached credentials for '{}' removed", role);
+            } else {
+                println!("No cached credentials for '{}'", role);
+            }
+        }
+
+        CacheCommands::Clear { role: None } => {
+            let removed = cache.clear()?;
+            println!("Removed {} cached session(s)", removed);
+        }
+
+        CacheCommands::Prune => {
+            let removed = cache.prune()?;
+            println!("Removed {} expired session(s)", removed);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_table(listings: &[CacheListing]) {
+    if listings.is_empty() {
+        println!("No cached credentials");
+        return;
+    }
+
+    let role_width = listings
+        .iter()
+        .map(|l| l.role.len())
+        .chain(std::iter::once("ROLE".len()))
+        .max()
+        .unwrap_or_default();
+    let identity_width = listings
+        .iter()
+        .map(|l| l.identity_arn.as_deref().unwrap_or("-").len())
+        .chain(std::iter::once("IDENTITY".len()))
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<role_width$}  {:<identity_width$}  {:<20}  REMAINING",
+        "ROLE", "IDENTITY", "ISSUED"
+    );
+    for listing in listings {
+        let mut remaining = if listing.expired {
+            "expired".to_string()
+        } else {
+            format_remaining(listing.remaining_seconds)
+        };
+        if listing.session_policy || !listing.policy_arns.is_empty() {
+            remaining.push_str(" (narrowed by session policy)");
+        }
+        println!(
+            "{:<role_width$}  {:<identity_width$}  {:<20}  {}",
+            listing.role,
+            listing.identity_arn.as_deref().unwrap_or("-"),
+            listing.issued_at,
+            remaining
+        );
+    }
+}
+
+/// Render a number of seconds as e.g. `1h 05m` or `4m 10s`.
+fn format_remaining(seconds: u64) -> String {
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else {
+        format!("{}m {:02}s", minutes, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(3900), "1h 05m");
+        assert_eq!(format_remaining(250), "4m 10s");
+        assert_eq!(format_remaining(0), "0m 00s");
+    }
+}
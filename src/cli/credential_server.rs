@@ -0,0 +1,386 @@
+use crate::aws::{format_timestamp, Credentials};
+use crate::error::{AppError, AppResult};
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request head accepted; SDK requests are a few hundred bytes.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// How long a client gets to send its request before it is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SDKs re-fetch container credentials once they are within 15 minutes of
+/// expiring (botocore again at 10), and reject a response that is no newer.
+const SDK_REFRESH_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Share of a session's remaining lifetime it is always served for before a
+/// refresh, so short sessions are not re-assumed on every request.
+const MIN_SERVED_FRACTION: u32 = 2;
+
+/// A loopback HTTP endpoint implementing the container credentials protocol,
+/// so SDKs in a child process fetch credentials from awsr (and fetch them
+/// again before they expire) instead of reading fixed environment variables.
+pub struct CredentialServer {
+    listener: TcpListener,
+    token: String,
+    credentials: Credentials,
+    margin: Duration,
+    refresh_at: Option<SystemTime>,
+}
+
+impl CredentialServer {
+    /// Listen on an ephemeral loopback port. `credentials` are served until
+    /// they are within `margin`, or the SDKs' 15-minute refresh window if that
+    /// is longer, of expiring, but always for at least half their lifetime.
+    pub async fn bind(credentials: Credentials, margin: Duration) -> AppResult<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .map_err(|e| AppError::from(e).with_context("Failed to start credential server"))?;
+
+        let margin = margin.max(SDK_REFRESH_WINDOW);
+        Ok(Self {
+            listener,
+            token: generate_token()?,
+            refresh_at: refresh_at(&credentials, margin),
+            credentials,
+            margin,
+        })
+    }
+
+    /// Value for `AWS_CONTAINER_CREDENTIALS_FULL_URI`.
+    pub fn uri(&self) -> AppResult<String> {
+        Ok(format!("http://{}/", self.listener.local_addr()?))
+    }
+
+    /// Value for `AWS_CONTAINER_AUTHORIZATION_TOKEN`, which clients must send
+    /// in their `Authorization` header.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Answer requests one at a time until the future is dropped, calling
+    /// `refresh` when the current credentials are about to expire.
+    pub async fn serve<F, Fut>(mut self, mut refresh: F) -> Infallible
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = AppResult<Credentials>>,
+    {
+        loop {
+            let mut stream = match self.listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("⚠️  Credential server: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            let response = match read_request(&mut stream).await {
+                Some(request) => self.respond(&request, &mut refresh).await,
+                None => continue,
+            };
+            // The client may already be gone; it will simply retry
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    }
+
+    async fn respond<F, Fut>(&mut self, request: &Request, refresh: &mut F) -> String
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = AppResult<Credentials>>,
+    {
+        if request.method != "GET" {
+            return http_response(
+                "405 Method Not Allowed",
+                &error_body("InvalidMethod", "Only GET is supported"),
+            );
+        }
+        let authorized = request
+            .authorization
+            .as_deref()
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()));
+        if !authorized {
+            return http_response(
+                "403 Forbidden",
+                &error_body("AccessDenied", "Missing or invalid authorization token"),
+            );
+        }
+
+        if self
+            .refresh_at
+            .is_some_and(|refresh_at| refresh_at <= SystemTime::now())
+        {
+            match refresh().await {
+                Ok(credentials) => {
+                    self.refresh_at = refresh_at(&credentials, self.margin);
+                    self.credentials = credentials;
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Could not refresh credentials: {}", e);
+                    if expires_within(&self.credentials, Duration::ZERO) {
+                        return http_response(
+                            "500 Internal Server Error",
+                            &error_body("RefreshFailed", &e.to_string()),
+                        );
+                    }
+                }
+            }
+        }
+
+        http_response("200 OK", &credentials_body(&self.credentials))
+    }
+}
+
+struct Request {
+    method: String,
+    authorization: Option<String>,
+}
+
+/// Read the request head. Bodies are ignored since only GET is served.
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut head = Vec::new();
+    let read = async {
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await.ok()?;
+            if n == 0 || head.len() + n > MAX_REQUEST_BYTES {
+                return None;
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+        Some(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read).await.ok()??;
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.split("\r\n");
+    let method = lines.next()?.split(' ').next()?.to_string();
+    let authorization = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Some(Request {
+        method,
+        authorization,
+    })
+}
+
+/// When to replace `credentials`: `margin` before they expire, shortened so
+/// that they are first served for a share of their remaining lifetime.
+fn refresh_at(credentials: &Credentials, margin: Duration) -> Option<SystemTime> {
+    let expiration = credentials.expiration?;
+    let lifetime = expiration
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    Some(expiration - refresh_margin(margin, lifetime))
+}
+
+fn refresh_margin(margin: Duration, lifetime: Duration) -> Duration {
+    margin.min(lifetime / MIN_SERVED_FRACTION)
+}
+
+fn expires_within(credentials: &Credentials, margin: Duration) -> bool {
+    credentials
+        .expiration
+        .is_some_and(|expiration| expiration <= SystemTime::now() + margin)
+}
+
+/// The response body the SDKs' container credentials providers expect.
+fn credentials_body(credentials: &Credentials) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "AccessKeyId": credentials.access_key_id,
+        "SecretAccessKey": credentials.secret_access_key,
+    });
+    if let Some(token) = &credentials.session_token {
+        body["Token"] = token.clone().into();
+    }
+    if let Some(expiration) = credentials.expiration {
+        body["Expiration"] = format_timestamp(expiration).into();
+    }
+    if let Some(role_arn) = &credentials.assumed_role_arn {
+        body["RoleArn"] = role_arn.clone().into();
+    }
+    body
+}
+
+fn error_body(code: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({ "code": code, "message": message })
+}
+
+fn http_response(status: &str, body: &serde_json::Value) -> String {
+    let body = body.to_string();
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// A random 256-bit token, hex encoded.
+fn generate_token() -> AppResult<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| {
+        AppError::CliError(format!("Failed to generate credential server token: {}", e))
+    })?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Compare tokens without leaking the length of the matching prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn credentials(access_key_id: &str, expires_in: Duration) -> Credentials {
+        Credentials {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expiration: Some(SystemTime::now() + expires_in),
+            assumed_role_arn: None,
+            source_identity: None,
+        }
+    }
+
+    async fn get(uri: &str, authorization: Option<&str>) -> String {
+        let address = uri.trim_start_matches("http://").trim_end_matches('/');
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut request = "GET / HTTP/1.1\r\nHost: 127.0.0.1\r\n".to_string();
+        if let Some(token) = authorization {
+            request.push_str(&format!("Authorization: {}\r\n", token));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serves_and_refreshes_credentials() {
+        let margin = Duration::from_secs(300);
+        let server = CredentialServer::bind(credentials("ASIAOLD", Duration::ZERO), margin)
+            .await
+            .unwrap();
+        let uri = server.uri().unwrap();
+        let token = server.token().to_string();
+        assert!(uri.starts_with("http://127.0.0.1:"));
+        assert_eq!(token.len(), 64);
+
+        let refreshes = AtomicUsize::new(0);
+        let refresh = || async {
+            refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(credentials("ASIANEW", Duration::from_secs(3600)))
+        };
+
+        let requests = async {
+            let denied = get(&uri, None).await;
+            assert!(denied.starts_with("HTTP/1.1 403"));
+            let denied = get(&uri, Some("wrong")).await;
+            assert!(denied.starts_with("HTTP/1.1 403"));
+
+            for _ in 0..2 {
+                let response = get(&uri, Some(&token)).await;
+                assert!(response.starts_with("HTTP/1.1 200"));
+                let body = response.split("\r\n\r\n").nth(1).unwrap();
+                let body: serde_json::Value = serde_json::from_str(body).unwrap();
+                assert_eq!(body["AccessKeyId"], "ASIANEW");
+                assert_eq!(body["Token"], "token");
+                assert!(body["Expiration"].as_str().unwrap().ends_with('Z'));
+            }
+        };
+
+        tokio::select! {
+            _ = server.serve(refresh) => unreachable!(),
+            _ = requests => {}
+        }
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_refresh_margin() {
+        // The cache margin is shorter, but an SDK asks again 10-15 minutes out
+        let margin = Duration::from_secs(300).max(SDK_REFRESH_WINDOW);
+        let hour = Duration::from_secs(3600);
+        assert_eq!(refresh_margin(margin, hour), SDK_REFRESH_WINDOW);
+        assert_eq!(
+            refresh_margin(Duration::from_secs(1800), 12 * hour),
+            Duration::from_secs(1800)
+        );
+        // A minimum-length session is served for half its lifetime
+        assert_eq!(
+            refresh_margin(margin, Duration::from_secs(900)),
+            Duration::from_secs(450)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_short_session_is_not_refreshed_immediately() {
+        let margin = Duration::from_secs(300);
+        let server =
+            CredentialServer::bind(credentials("ASIAOLD", Duration::from_secs(900)), margin)
+                .await
+                .unwrap();
+        let uri = server.uri().unwrap();
+        let token = server.token().to_string();
+
+        let refreshes = AtomicUsize::new(0);
+        let refresh = || async {
+            refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(credentials("ASIANEW", Duration::from_secs(900)))
+        };
+
+        let requests = async {
+            for _ in 0..3 {
+                let response = get(&uri, Some(&token)).await;
+                assert!(response.starts_with("HTTP/1.1 200"));
+                assert!(response.contains("ASIAOLD"));
+            }
+        };
+
+        tokio::select! {
+            _ = server.serve(refresh) => unreachable!(),
+            _ = requests => {}
+        }
+        assert_eq!(refreshes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_serves_unexpired_credentials() {
+        let margin = Duration::from_secs(300);
+        let mut server =
+            CredentialServer::bind(credentials("ASIAOLD", Duration::from_secs(60)), margin)
+                .await
+                .unwrap();
+        // As if the credentials had aged into their refresh window
+        server.refresh_at = Some(SystemTime::now());
+        let uri = server.uri().unwrap();
+        let token = server.token().to_string();
+        let refresh = || async { Err(AppError::AwsError("throttled".to_string())) };
+
+        tokio::select! {
+            _ = server.serve(refresh) => unreachable!(),
+            response = get(&uri, Some(&token)) => {
+                assert!(response.starts_with("HTTP/1.1 200"));
+                assert!(response.contains("ASIAOLD"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
@@ -0,0 +1,403 @@
+use super::credential_server::CredentialServer;
+use super::EnvArgs;
+use crate::aws::{format_timestamp, Credentials};
+use crate::config::RoleConfig;
+use crate::error::{AppError, AppResult};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::future::Future;
+use std::process::ExitStatus;
+use tokio::process::{Child, Command};
+
+/// Inherited variables that would make SDKs in the child pick other
+/// credentials, or describe credentials it no longer has.
+const CONFLICTING_VARIABLES: &[&str] = &[
+    "AWS_PROFILE",
+    "AWS_DEFAULT_PROFILE",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_SECURITY_TOKEN",
+    "AWS_CREDENTIAL_EXPIRATION",
+    "AWS_ROLE_ARN",
+    "AWS_ROLE_SESSION_NAME",
+    "AWS_WEB_IDENTITY_TOKEN_FILE",
+    "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+    "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+    "AWS_CONTAINER_AUTHORIZATION_TOKEN",
+    "AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE",
+    "AWSR_ROLE",
+];
+
+/// Variables kept by `--clean-env` so programs can still be found and run.
+const CLEAN_ENV_VARIABLES: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "TERM",
+    "TZ",
+    "TMPDIR",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    // Windows
+    "SYSTEMROOT",
+    "COMSPEC",
+    "PATHEXT",
+    "USERPROFILE",
+    "TEMP",
+    "TMP",
+    "APPDATA",
+    "LOCALAPPDATA",
+];
+
+/// A command to run with a role's credentials.
+pub struct ChildCommand<'a> {
+    /// Program and arguments, passed verbatim unless `shell` is set
+    pub command: &'a [String],
+    /// Join the words and run them through `$SHELL -c`, so pipes, globs and
+    /// variables work
+    pub shell: bool,
+    pub role: &'a RoleConfig,
+    pub region: Option<&'a str>,
+    pub env: &'a EnvArgs,
+}
+
+/// How the child receives its credentials.
+enum CredentialVars<'a> {
+    /// Fixed `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, ... variables
+    Static(&'a Credentials),
+    /// A container credentials endpoint the SDKs query, and re-query
+    Endpoint { uri: &'a str, token: &'a str },
+}
+
+/// Run the command with `credentials` in its environment and return the exit
+/// code awsr should exit with.
+pub async fn run_with_credentials(
+    child: &ChildCommand<'_>,
+    credentials: &Credentials,
+) -> AppResult<i32> {
+    let mut process = spawn(child, CredentialVars::Static(credentials))?;
+    let status = wait_forwarding_signals(&mut process).await;
+    finish(status)
+}
+
+/// Run the command with `server` as its credential source, refreshing the
+/// credentials through `refresh` while it runs. The server stops when the
+/// command exits.
+pub async fn run_with_credential_server<F, Fut>(
+    child: &ChildCommand<'_>,
+    server: CredentialServer,
+    refresh: F,
+) -> AppResult<i32>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AppResult<Credentials>>,
+{
+    let uri = server.uri()?;
+    let token = server.token().to_string();
+    let mut process = spawn(
+        child,
+        CredentialVars::Endpoint {
+            uri: &uri,
+            token: &token,
+        },
+    )?;
+
+    let status = tokio::select! {
+        status = wait_forwarding_signals(&mut process) => status,
+        never = server.serve(refresh) => match never {},
+    };
+    finish(status)
+}
+
+fn spawn(child: &ChildCommand<'_>, credentials: CredentialVars<'_>) -> AppResult<Child> {
+    let mut command = build_command(child.command, child.shell)?;
+    command.env_clear();
+    command.envs(child_environment(
+        std::env::vars_os(),
+        credentials,
+        child.role,
+        child.region,
+        child.env,
+    ));
+
+    command.spawn().map_err(|e| {
+        AppError::CliError(format!(
+            "Failed to execute '{}': {}",
+            child.command.join(" "),
+            e
+        ))
+    })
+}
+
+fn finish(status: std::io::Result<ExitStatus>) -> AppResult<i32> {
+    let status =
+        status.map_err(|e| AppError::CliError(format!("Failed to wait for command: {}", e)))?;
+    Ok(exit_code(status))
+}
+
+/// Build the child's environment from the inherited one: drop conflicting
+/// variables (or everything but the basics with `--clean-env`), add the role's
+/// credentials, then apply `--unset` and `--env`.
+fn child_environment(
+    inherited: impl IntoIterator<Item = (OsString, OsString)>,
+    credentials: CredentialVars<'_>,
+    role: &RoleConfig,
+    region: Option<&str>,
+    env: &EnvArgs,
+) -> BTreeMap<OsString, OsString> {
+    let is_any_of = |key: &OsString, names: &[&str]| {
+        key.to_str()
+            .is_some_and(|key| names.iter().any(|name| same_variable(name, key)))
+    };
+
+    let mut vars: BTreeMap<OsString, OsString> = inherited
+        .into_iter()
+        .filter(|(key, _)| !is_any_of(key, CONFLICTING_VARIABLES))
+        .filter(|(key, _)| !env.clean_env || is_any_of(key, CLEAN_ENV_VARIABLES))
+        .collect();
+
+    let mut set = |key: &str, value: &str| {
+        vars.insert(key.into(), value.into());
+    };
+    match credentials {
+        CredentialVars::Static(credentials) => {
+            set("AWS_ACCESS_KEY_ID", &credentials.access_key_id);
+            set("AWS_SECRET_ACCESS_KEY", &credentials.secret_access_key);
+            if let Some(token) = &credentials.session_token {
+                set("AWS_SESSION_TOKEN", token);
+            }
+            if let Some(expiration) = credentials.expiration {
+                set("AWS_CREDENTIAL_EXPIRATION", &format_timestamp(expiration));
+            }
+        }
+        CredentialVars::Endpoint { uri, token } => {
+            set("AWS_CONTAINER_CREDENTIALS_FULL_URI", uri);
+            set("AWS_CONTAINER_AUTHORIZATION_TOKEN", token);
+        }
+    }
+    if let Some(region) = region {
+        set("AWS_REGION", region);
+        set("AWS_DEFAULT_REGION", region);
+    }
+    set("AWSR_ROLE", &role.name);
+
+    for key in &env.unset {
+        vars.retain(|existing, _| !is_any_of(existing, &[key]));
+    }
+    for (key, value) in &env.set {
+        vars.insert(key.into(), value.into());
+    }
+    vars
+}
+
+/// Variable names are case-insensitive on Windows only.
+fn same_variable(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+fn build_command(command: &[String], shell: bool) -> AppResult<Command> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| AppError::CliError("Empty command".to_string()))?;
+
+    if !shell {
+        let mut child = Command::new(program);
+        child.args(args);
+        return Ok(child);
+    }
+
+    let script = command.join(" ");
+    let child = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", &script]);
+        c
+    } else {
+        let shell = std::env::var("SHELL")
+            .ok()
+            .filter(|shell| !shell.is_empty())
+            .unwrap_or_else(|| "sh".to_string());
+        let mut c = Command::new(shell);
+        c.args(["-c", &script]);
+        c
+    };
+    Ok(child)
+}
+
+/// Wait for the child, passing on SIGINT and SIGTERM so it can shut down
+/// cleanly instead of being orphaned when awsr is stopped.
+#[cfg(unix)]
+async fn wait_forwarding_signals(child: &mut Child) -> std::io::Result<ExitStatus> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        let signal = tokio::select! {
+            status = child.wait() => return status,
+            _ = interrupt.recv() => libc::SIGINT,
+            _ = terminate.recv() => libc::SIGTERM,
+        };
+        if let Some(pid) = child.id() {
+            // SAFETY: kill(2) has no memory-safety requirements
+            unsafe {
+                libc::kill(pid as libc::pid_t, signal);
+            }
+        }
+    }
+}
+
+/// Ctrl+C already reaches every process on the console, so awsr only has to
+/// outlive the child.
+#[cfg(not(unix))]
+async fn wait_forwarding_signals(child: &mut Child) -> std::io::Result<ExitStatus> {
+    loop {
+        tokio::select! {
+            status = child.wait() => return status,
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+}
+
+/// The child's exit code, or 128+N when it was killed by signal N, as shells
+/// report it.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            access_key_id: "ASIAEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            expiration: None,
+            assumed_role_arn: None,
+            source_identity: None,
+        }
+    }
+
+    fn role() -> RoleConfig {
+        RoleConfig {
+            name: "dev".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    async fn run(command: &[&str], shell: bool) -> AppResult<i32> {
+        let command = argv(command);
+        let child = ChildCommand {
+            command: &command,
+            shell,
+            role: &role(),
+            region: None,
+            env: &EnvArgs::default(),
+        };
+        run_with_credentials(&child, &credentials()).await
+    }
+
+    fn environment(inherited: &[(&str, &str)], env: &EnvArgs) -> BTreeMap<String, String> {
+        let inherited = inherited
+            .iter()
+            .map(|(key, value)| (OsString::from(key), OsString::from(value)));
+        let credentials = Credentials {
+            session_token: Some("token".to_string()),
+            expiration: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)),
+            ..credentials()
+        };
+        let credentials = CredentialVars::Static(&credentials);
+        child_environment(inherited, credentials, &role(), Some("eu-west-1"), env)
+            .into_iter()
+            .map(|(key, value)| (key.into_string().unwrap(), value.into_string().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_child_environment() {
+        let inherited = [
+            ("PATH", "/usr/bin"),
+            ("EDITOR", "vi"),
+            ("AWS_PROFILE", "stale"),
+            ("AWS_SECURITY_TOKEN", "stale"),
+            ("AWS_CREDENTIAL_EXPIRATION", "2000-01-01T00:00:00Z"),
+            ("AWS_REGION", "us-east-1"),
+            ("AWS_PAGER", ""),
+            ("aws_profile", "unrelated"),
+        ];
+
+        let vars = environment(&inherited, &EnvArgs::default());
+        assert_eq!(vars["PATH"], "/usr/bin");
+        assert_eq!(vars["EDITOR"], "vi");
+        assert_eq!(vars["AWS_PAGER"], "");
+        assert!(!vars.contains_key("AWS_PROFILE"));
+        assert!(!vars.contains_key("AWS_SECURITY_TOKEN"));
+        assert_eq!(vars["aws_profile"], "unrelated");
+        assert_eq!(vars["AWS_ACCESS_KEY_ID"], "ASIAEXAMPLE");
+        assert_eq!(vars["AWS_SESSION_TOKEN"], "token");
+        assert_eq!(vars["AWS_CREDENTIAL_EXPIRATION"], "2023-11-14T22:13:20Z");
+        assert_eq!(vars["AWS_REGION"], "eu-west-1");
+        assert_eq!(vars["AWS_DEFAULT_REGION"], "eu-west-1");
+        assert_eq!(vars["AWSR_ROLE"], "dev");
+
+        let env = EnvArgs {
+            set: vec![("EDITOR".to_string(), "nano".to_string())],
+            unset: vec!["AWS_DEFAULT_REGION".to_string(), "aws_region".to_string()],
+            clean_env: true,
+        };
+        let vars = environment(&inherited, &env);
+        assert_eq!(vars["PATH"], "/usr/bin");
+        assert_eq!(vars["EDITOR"], "nano");
+        assert!(!vars.contains_key("AWS_PAGER"));
+        assert!(!vars.contains_key("AWS_DEFAULT_REGION"));
+        assert_eq!(vars["AWS_REGION"], "eu-west-1");
+        assert_eq!(vars["AWSR_ROLE"], "dev");
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_propagation() {
+        assert_eq!(run(&["sh", "-c", "exit 7"], false).await.unwrap(), 7);
+        assert_eq!(run(&["exit 3"], true).await.unwrap(), 3);
+        assert_eq!(
+            run(&["sh", "-c", "kill -TERM $$"], false).await.unwrap(),
+            128 + libc::SIGTERM
+        );
+    }
+
+    #[tokio::test]
+    async fn test_arguments_passed_verbatim() {
+        let check = r#"[ "$1" = "my file" ] && [ "$2" = "\$HOME" ] && [ "$AWS_ACCESS_KEY_ID" = ASIAEXAMPLE ]"#;
+        let code = run(&["sh", "-c", check, "sh", "my file", "$HOME"], false)
+            .await
+            .unwrap();
+        assert_eq!(code, 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_program() {
+        let err = run(&["awsr-no-such-program"], false).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Failed to execute 'awsr-no-such-program'"));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::aws::profile;
+use crate::config::{Config, RoleConfig};
+use crate::error::{AppError, AppResult};
+use clap::Subcommand;
+use std::path::PathBuf;
+
+#[derive(Subcommand)]
+pub enum ImportCommands {
+    /// Import role profiles from an AWS CLI config file
+    AwsConfig {
+        /// Config file to read (default: ~/.aws/config)
+        #[arg(long, help = "AWS config file to import from (default: ~/.aws/config)")]
+        path: Option<PathBuf>,
+
+        /// Only import profiles whose name matches this glob
+        #[arg(long, help = "Glob on profile names, e.g. 'prod-*'")]
+        filter: Option<String>,
+
+        /// Show what would change without saving
+        #[arg(long, help = "Show what would be imported without saving")]
+        dry_run: bool,
+
+        /// Replace existing roles that differ from the imported profile
+        #[arg(long, help = "Overwrite conflicting roles instead of skipping them")]
+        overwrite: bool,
+    },
+}
+
+/// What importing a set of profiles would do to the configuration.
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub added: Vec<RoleConfig>,
+    pub unchanged: Vec<String>,
+    /// Imported roles whose name is already configured with different settings
+    pub conflicts: Vec<RoleConfig>,
+    /// Profiles that could not be imported, with the reason
+    pub invalid: Vec<(String, String)>,
+}
+
+/// Work out which role profiles in `content` are new, unchanged or conflicting.
+pub fn plan_import(config: &Config, content: &str, filter: Option<&str>) -> AppResult<ImportPlan> {
+    let pattern = filter
+        .map(glob::Pattern::new)
+        .transpose()
+        .map_err(|e| AppError::CliError(format!("Invalid filter: {}", e)))?;
+
+    let mut plan = ImportPlan::default();
+    for (name, properties) in profile::parse_profiles(content, true) {
+        if pattern.as_ref().is_some_and(|p| !p.matches(&name)) {
+            continue;
+        }
+
+        let role = match profile::role_from_profile(&name, &properties) {
+            Ok(Some(role)) => role,
+            Ok(None) => continue,
+            Err(e) => {
+                plan.invalid.push((name, e.to_string()));
+                continue;
+            }
+        };
+
+        match config.get_role(&role.name) {
+            None => plan.added.push(role),
+            Some(existing) if *existing == role => plan.unchanged.push(role.name),
+            Some(_) => plan.conflicts.push(role),
+        }
+    }
+
+    Ok(plan)
+}
+
+pub fn run(config: &mut Config, command: &ImportCommands) -> AppResult<()> {
+    match command {
+        ImportCommands::AwsConfig {
+            path,
+            filter,
+            dry_run,
+            overwrite,
+        } => {
+            let path = match path {
+                Some(path) => path.clone(),
+                None => profile::config_file_path()?,
+            };
+            let content = profile::read_optional(&path)?.ok_or_else(|| {
+                AppError::ConfigError(format!("AWS config file {} not found", path.display()))
+            })?;
+
+            let plan = plan_import(config, &content, filter.as_deref())?;
+            let prefix = if *dry_run { "Would import" } else { "Imported" };
+
+            for role in &plan.added {
+                println!("➕ {} '{}' ({})", prefix, role.name, role.role_arn);
+            }
+            for name in &plan.unchanged {
+                println!("✔️  '{}' is already up to date", name);
+            }
+            for role in &plan.conflicts {
+                if *overwrite {
+                    println!("♻️  {} '{}' over the existing role", prefix, role.name);
+                } else {
+                    println!(
+                        "⚠️  Conflict: role '{}' already exists with different settings (use --overwrite to replace)",
+                        role.name
+                    );
+                }
+            }
+            for (name, reason) in &plan.invalid {
+                println!("❌ Skipped profile '{}': {}", name, reason);
+            }
+
+            let mut imported = plan.added;
+            if *overwrite {
+                imported.extend(plan.conflicts);
+            }
+
+            if *dry_run {
+                println!("\nDry run: {} role(s) would be imported", imported.len());
+                return Ok(());
+            }
+
+            let count = imported.len();
+            if count > 0 {
+                for role in imported {
+                    config.add_role(role);
+                }
+                config.save()?;
+            }
+            println!("\n✅ Imported {} role(s) from {}", count, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWS_CONFIG: &str = r#"
+[default]
+region = us-east-1
+
+[profile dev]
+role_arn = arn:aws:iam::123456789012:role/DevRole
+source_profile = default
+
+[profile prod-eu]
+role_arn = arn:aws:iam::987654321098:role/ProdRole
+source_profile = default
+
+[profile prod-us]
+role_arn = arn:aws:iam::987654321098:role/ProdRole
+duration_seconds = forever
+"#;
+
+    #[test]
+    fn test_plan_import() {
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "dev".to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/DevRole".to_string(),
+            account_id: "123456789012".to_string(),
+            source_profile: Some("default".to_string()),
+            ..Default::default()
+        });
+        config.add_role(RoleConfig {
+            name: "prod-eu".to_string(),
+            role_arn: "arn:aws:iam::987654321098:role/OldRole".to_string(),
+            account_id: "987654321098".to_string(),
+            ..Default::default()
+        });
+
+        let plan = plan_import(&config, AWS_CONFIG, None).unwrap();
+        assert!(plan.added.is_empty());
+        assert_eq!(plan.unchanged, vec!["dev"]);
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].name, "prod-eu");
+        assert_eq!(plan.invalid.len(), 1);
+        assert_eq!(plan.invalid[0].0, "prod-us");
+    }
+
+    #[test]
+    fn test_plan_import_filter() {
+        let plan = plan_import(&Config::new(), AWS_CONFIG, Some("prod-*")).unwrap();
+        let added: Vec<&str> = plan.added.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(added, vec!["prod-eu"]);
+        assert_eq!(plan.invalid.len(), 1);
+
+        assert!(plan_import(&Config::new(), AWS_CONFIG, Some("[")).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::config::RoleConfig;
+use crate::error::{AppError, AppResult};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Obtain the MFA code for `role`, if it has an `mfa_serial` configured.
+///
+/// An explicit `--mfa-code` wins, then `--mfa-command`, and finally the user is
+/// prompted. The prompt goes to stderr so `eval $(awsr assume ...)` keeps working.
+pub fn resolve_mfa_code(
+    role: &RoleConfig,
+    mfa_code: Option<&str>,
+    mfa_command: Option<&str>,
+) -> AppResult<Option<String>> {
+    let serial = match &role.mfa_serial {
+        Some(serial) => serial,
+        None => return Ok(None),
+    };
+
+    let code = if let Some(code) = mfa_code {
+        code.trim().to_string()
+    } else if let Some(command) = mfa_command {
+        run_mfa_command(command)?
+    } else {
+        prompt_mfa_code(serial)?
+    };
+
+    validate_mfa_code(&code)?;
+    Ok(Some(code))
+}
+
+/// STS expects the six-digit code shown by the device.
+pub fn validate_mfa_code(code: &str) -> AppResult<()> {
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err(AppError::CliError(
+            "Invalid MFA code: expected 6 digits".to_string(),
+        ))
+    }
+}
+
+fn prompt_mfa_code(serial: &str) -> AppResult<String> {
+    eprint!("🔐 Enter MFA code for {}: ", serial);
+    io::stderr().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Run an external program (e.g. `ykman oath accounts code -s aws`) and use
+/// the last line of its stdout as the code. Its stderr stays attached to the
+/// terminal so the program can prompt for touch or a PIN.
+fn run_mfa_command(command: &str) -> AppResult<String> {
+    let mut child = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", command]);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.args(["-c", command]);
+        c
+    };
+
+    let output = child
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| AppError::CliError(format!("Failed to run MFA command: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(AppError::CliError(format!(
+            "MFA command failed with exit code: {:?}",
+            output.status.code()
+        )));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mfa_role() -> RoleConfig {
+        RoleConfig {
+            name: "dev".to_string(),
+            mfa_serial: Some("arn:aws:iam::123456789012:mfa/user".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_mfa_code() {
+        assert!(validate_mfa_code("123456").is_ok());
+        assert!(validate_mfa_code("12345").is_err());
+        assert!(validate_mfa_code("12345a").is_err());
+        assert!(validate_mfa_code("").is_err());
+    }
+
+    #[test]
+    fn test_no_mfa_serial_needs_no_code() {
+        let role = RoleConfig::default();
+        assert_eq!(resolve_mfa_code(&role, None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_explicit_code_wins() {
+        let code = resolve_mfa_code(&mfa_role(), Some(" 654321 "), Some("exit 1")).unwrap();
+        assert_eq!(code, Some("654321".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mfa_command_output() {
+        let code =
+            resolve_mfa_code(&mfa_role(), None, Some("echo touch key; echo 123456")).unwrap();
+        assert_eq!(code, Some("123456".to_string()));
+
+        assert!(resolve_mfa_code(&mfa_role(), None, Some("exit 3")).is_err());
+    }
+}
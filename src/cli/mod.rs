@@ -1,7 +1,24 @@
-use crate::aws::{AwsClient, Credentials};
-use crate::config::{Config, RoleConfig};
+mod cache;
+mod credential_server;
+mod exec;
+mod import;
+mod mfa;
+mod sso;
+
+use crate::aws::arn::Arn;
+use crate::aws::profile::{self, ExportMode};
+use crate::aws::{
+    format_timestamp, resolve_session_policy, sanitize_session_name_value, validate_policy_arns,
+    validate_role_session_name, validate_session_tags, validate_source_identity, AssumeRoleOptions,
+    AwsClient, Credentials, RoleAccess,
+};
+use crate::cache::{CacheKey, CredentialCache, DEFAULT_EXPIRY_MARGIN_SECONDS};
+use crate::config::template::{uses_placeholder, TemplateContext};
+use crate::config::{Config, RoleConfig, RoleKind, SessionOverrides};
 use crate::error::AppResult;
 use clap::{Parser, Subcommand};
+use credential_server::CredentialServer;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -57,6 +74,15 @@ EXAMPLES:
   # With specific source profile
   awsr configure -n prod -r arn:aws:iam::987654321098:role/ProdRole -a 987654321098 -s my-profile
 
+  # Role whose trust policy requires MFA
+  awsr configure -n prod -r arn:aws:iam::987654321098:role/ProdRole -a 987654321098 --mfa-serial arn:aws:iam::123456789012:mfa/me
+
+  # Third-party role that requires an external ID
+  awsr configure -n vendor -r arn:aws:iam::555555555555:role/VendorRole -a 555555555555 --external-id abc-123
+
+  # IAM Identity Center permission set (requires sso_start_url/sso_region and 'awsr sso login')
+  awsr configure -n admin -a 123456789012 --sso-role-name AdministratorAccess
+
 ROLE REQUIREMENTS:
   - The role must exist in the target AWS account
   - The role's trust policy must allow your current identity to assume it
@@ -70,21 +96,139 @@ ROLE REQUIREMENTS:
         #[arg(
             short,
             long,
+            required_unless_present = "sso_role_name",
             help = "Full ARN of the IAM role (arn:aws:iam::ACCOUNT:role/ROLE-NAME)"
         )]
-        role_arn: String,
+        role_arn: Option<String>,
+
+        /// IAM Identity Center permission set, instead of a role ARN
+        #[arg(
+            long,
+            conflicts_with_all = ["role_arn", "mfa_serial", "external_id", "parent_role"],
+            help = "Permission set name for an SSO role (uses sso_start_url and sso_region from the config)"
+        )]
+        sso_role_name: Option<String>,
 
         /// AWS Account ID where the role exists
         #[arg(short, long, help = "12-digit AWS account ID")]
         account_id: String,
 
         /// Source AWS profile to use (optional)
-        #[arg(short, long, help = "AWS profile name from ~/.aws/credentials")]
+        #[arg(
+            short,
+            long,
+            help = "AWS profile from ~/.aws/config or ~/.aws/credentials used to assume the role"
+        )]
         source_profile: Option<String>,
 
         /// Session duration in seconds (optional, default: 3600)
-        #[arg(long, help = "Session duration in seconds (900-43200, default: 3600)")]
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(i64).range(900..=43200),
+            help = "Session duration in seconds (900-43200, default: 3600)"
+        )]
         session_duration: Option<i64>,
+
+        /// MFA device required by the role (optional)
+        #[arg(
+            long,
+            help = "ARN or serial number of the MFA device required by the role"
+        )]
+        mfa_serial: Option<String>,
+
+        /// External ID required by the role's trust policy (optional)
+        #[arg(
+            long,
+            help = "ExternalId sent with every AssumeRole call for this role"
+        )]
+        external_id: Option<String>,
+
+        /// Session name template for this role (optional)
+        #[arg(
+            long,
+            help = "Session name template, e.g. '{user}@{hostname}' (placeholders: {user}, {hostname}, {role}, {timestamp}, {user_id}, {principal})"
+        )]
+        session_name: Option<String>,
+
+        /// SourceIdentity template for this role (optional)
+        #[arg(
+            long,
+            conflicts_with = "sso_role_name",
+            help = "SourceIdentity template, e.g. '{principal}' for the SSO user or local user ('' disables a global one)"
+        )]
+        source_identity: Option<String>,
+
+        /// AWS region for this role's sessions (optional)
+        #[arg(
+            long,
+            help = "Region used for STS calls and exported as AWS_REGION (default: default_region from the config)"
+        )]
+        region: Option<String>,
+
+        /// Use FIPS endpoints for this role (optional)
+        #[arg(long, help = "Call STS through its FIPS endpoint for this role")]
+        use_fips_endpoint: bool,
+
+        /// Use dual-stack endpoints for this role (optional)
+        #[arg(
+            long,
+            visible_alias = "use-dualstack",
+            help = "Call STS through its dual-stack (IPv4 and IPv6) endpoint for this role"
+        )]
+        use_dualstack_endpoint: bool,
+
+        /// Custom STS/SSO endpoint for this role (optional)
+        #[arg(
+            long,
+            value_parser = parse_endpoint_url,
+            help = "STS and SSO endpoint, e.g. http://localhost:4566 for LocalStack (default: endpoint_url from the config)"
+        )]
+        endpoint_url: Option<String>,
+
+        /// Session tags for this role (optional, repeatable)
+        #[arg(
+            long = "tag",
+            value_name = "KEY=VALUE",
+            value_parser = parse_key_value,
+            conflicts_with = "sso_role_name",
+            help = "Session tag sent when assuming the role, e.g. --tag team=platform (repeatable, value may use placeholders)"
+        )]
+        tags: Vec<(String, String)>,
+
+        /// Tag keys that persist through role chaining (optional, repeatable)
+        #[arg(
+            long = "transitive-tag-key",
+            value_name = "KEY",
+            conflicts_with = "sso_role_name",
+            help = "Key of a --tag that stays on the session when it assumes further roles (repeatable)"
+        )]
+        transitive_tag_keys: Vec<String>,
+
+        /// Inline session policy for this role (optional)
+        #[arg(
+            long,
+            value_name = "JSON|@FILE",
+            conflicts_with = "sso_role_name",
+            help = "Inline session policy JSON, or @path to a JSON file, that narrows the role's permissions"
+        )]
+        session_policy: Option<String>,
+
+        /// Managed session policies for this role (optional, repeatable)
+        #[arg(
+            long = "policy-arn",
+            value_name = "ARN",
+            conflicts_with = "sso_role_name",
+            help = "Managed policy ARN that narrows the role's permissions (repeatable)"
+        )]
+        policy_arns: Vec<String>,
+
+        /// Configured role to assume first (role chaining, optional)
+        #[arg(
+            long,
+            visible_alias = "via",
+            help = "Configured role whose credentials are used to assume this one"
+        )]
+        parent_role: Option<String>,
     },
 
     /// Assume a configured role and set credentials
@@ -104,9 +248,28 @@ EXAMPLES:
   # Execute a command with the assumed role
   awsr assume dev --exec "aws s3 ls"
 
+  # Role with MFA (prompts on stderr when no code is given)
+  awsr assume prod --mfa-code 123456
+  awsr assume prod --mfa-command "ykman oath accounts code -s aws"
+
+  # Custom session name shown in CloudTrail
+  awsr assume dev --session-name "{user}-{timestamp}"
+
+  # Ignore cached credentials and request a new session
+  awsr assume dev --refresh
+
 OUTPUT FORMATS:
   - export (default): Shell export statements for direct use
   - json: JSON format for programmatic use
+  - credential-process: AWS credential_process JSON (Version 1) for ~/.aws/config:
+      [profile dev]
+      credential_process = awsr assume dev --format credential-process
+  - credentials-file: Write the credentials into the ~/.aws/credentials profile
+    given by --profile-name, for tools that ignore environment variables
+
+Credentials are cached under ~/.aws-assume-role/cache and reused until they are
+close to expiry. Use --refresh to replace the cached session or --no-cache to
+bypass the cache entirely.
 
 The tool automatically detects your shell and outputs the appropriate format."#
     )]
@@ -115,25 +278,102 @@ The tool automatically detects your shell and outputs the appropriate format."#
         #[arg(help = "Role name from 'awsr list'")]
         name: String,
 
-        /// Session duration in seconds (default: 3600)
+        /// Output format for credentials
         #[arg(
             short,
             long,
-            help = "Session duration in seconds (900-43200, default: 3600)"
+            help = "Output format: 'export', 'json', 'credential-process' or 'credentials-file'. Defaults to shell-specific exports."
         )]
-        duration: Option<i32>,
+        format: Option<String>,
 
-        /// Output format for credentials
+        /// Profile written by --format credentials-file
         #[arg(
-            short,
             long,
-            help = "Output format: 'export' or 'json'. Defaults to shell-specific exports."
+            required_if_eq("format", "credentials-file"),
+            help = "Profile in ~/.aws/credentials to write (with --format credentials-file)"
         )]
-        format: Option<String>,
+        profile_name: Option<String>,
 
         /// Execute a command with the assumed role credentials
-        #[arg(short, long, help = "Command to execute with assumed role credentials")]
+        #[arg(
+            short,
+            long,
+            help = "Shell command to execute with assumed role credentials (see also 'awsr exec')"
+        )]
         exec: Option<String>,
+
+        #[command(flatten)]
+        session: SessionArgs,
+    },
+
+    /// Run a command with a role's credentials
+    #[command(
+        long_about = r#"Run a command with the credentials of a configured role.
+
+Everything after '--' is passed to the command as-is, so arguments keep their
+quoting. awsr exits with the command's exit code (128+N if it was killed by
+signal N) and forwards SIGINT and SIGTERM to it.
+
+EXAMPLES:
+  # Arguments are passed verbatim
+  awsr exec dev -- aws s3 cp 'my file' s3://bucket/
+
+  # Roles with MFA take the same options as 'awsr assume'
+  awsr exec prod --mfa-code 123456 -- terraform apply
+
+  # Run through $SHELL -c for pipes, globs and variables
+  awsr exec dev --shell -- 'aws s3 ls | wc -l'
+
+  # Control the rest of the environment
+  awsr exec dev --clean-env --env TF_LOG=debug -- terraform plan
+
+  # Keep a long-running job supplied with fresh credentials
+  awsr exec dev --server -- ./nightly-backup.sh
+
+ENVIRONMENT:
+  AWS_PROFILE, AWS_SECURITY_TOKEN and other inherited variables that would
+  select different credentials are removed. The command gets
+  AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN,
+  AWS_CREDENTIAL_EXPIRATION, AWS_REGION/AWS_DEFAULT_REGION (when the role has
+  a region) and AWSR_ROLE, the name of the assumed role. --unset and --env
+  are applied last.
+
+  With --server, the credentials are served instead from a loopback endpoint
+  given by AWS_CONTAINER_CREDENTIALS_FULL_URI and
+  AWS_CONTAINER_AUTHORIZATION_TOKEN. SDKs fetch them from there and fetch them
+  again before they expire, when awsr assumes the role anew (roles with MFA
+  need --mfa-command for that). The endpoint stops when the command exits."#
+    )]
+    Exec {
+        /// Name of the role configuration to assume
+        #[arg(help = "Role name from 'awsr list'")]
+        name: String,
+
+        /// Run the command through the user's shell
+        #[arg(long, help = "Run the command through $SHELL -c (cmd /C on Windows)")]
+        shell: bool,
+
+        /// Serve refreshing credentials from a local endpoint
+        #[arg(
+            long,
+            help = "Serve credentials from a local container credentials endpoint that refreshes them, for long-running commands"
+        )]
+        server: bool,
+
+        #[command(flatten)]
+        session: SessionArgs,
+
+        #[command(flatten)]
+        env: EnvArgs,
+
+        /// Command and its arguments
+        #[arg(
+            last = true,
+            required = true,
+            value_name = "COMMAND",
+            help = "Command and arguments to run, after '--'"
+        )]
+        command: Vec<String>,
     },
 
     /// List all configured roles
@@ -157,6 +397,121 @@ This only removes the local configuration. It does not affect the actual IAM rol
         name: String,
     },
 
+    /// Inspect and purge cached credentials
+    #[command(long_about = r#"Inspect and purge credentials cached by 'awsr assume'.
+
+EXAMPLES:
+  # Show cached sessions and their remaining lifetime
+  awsr cache list
+  awsr cache list --format json
+
+  # Evict one role, or everything
+  awsr cache clear dev
+  awsr cache clear
+
+  # Delete expired entries
+  awsr cache prune
+
+Secret values are never printed."#)]
+    Cache {
+        #[command(subcommand)]
+        command: cache::CacheCommands,
+    },
+
+    /// Generate ~/.aws/config profiles from configured roles
+    #[command(
+        long_about = r#"Generate '[profile <name>]' sections for every configured role.
+
+By default each profile lets the AWS CLI/SDKs assume the role themselves
+(role_arn, source_profile, duration_seconds, mfa_serial, external_id). With
+--credential-process each profile calls back into awsr instead.
+
+EXAMPLES:
+  # Print profiles to stdout
+  awsr export-profiles
+
+  # Write them to a file
+  awsr export-profiles --output ./aws-profiles.ini
+
+  # Merge into ~/.aws/config between managed marker comments
+  awsr export-profiles --credential-process --merge
+
+Merging only rewrites the block between the markers. Sections outside it are
+left untouched; roles whose profile name is already defined there are skipped."#
+    )]
+    ExportProfiles {
+        /// Use credential_process = awsr ... instead of role_arn settings
+        #[arg(long, help = "Emit a credential_process line pointing back at awsr")]
+        credential_process: bool,
+
+        /// Command used in credential_process lines
+        #[arg(
+            long,
+            requires = "credential_process",
+            help = "awsr binary to call from credential_process (default: this executable)"
+        )]
+        command: Option<String>,
+
+        /// Write to this file instead of stdout
+        #[arg(
+            short,
+            long,
+            conflicts_with = "merge",
+            help = "Write profiles to this file"
+        )]
+        output: Option<std::path::PathBuf>,
+
+        /// Merge into ~/.aws/config
+        #[arg(
+            long,
+            help = "Merge into ~/.aws/config between managed marker comments"
+        )]
+        merge: bool,
+    },
+
+    /// Import roles from other tools
+    #[command(long_about = r#"Import role configurations from other tools.
+
+EXAMPLES:
+  # Import every role_arn profile from ~/.aws/config
+  awsr import aws-config
+
+  # Preview importing only production profiles from another file
+  awsr import aws-config --path ./team-config --filter 'prod-*' --dry-run
+
+Roles that already exist with different settings are reported as conflicts and
+left unchanged unless --overwrite is given."#)]
+    Import {
+        #[command(subcommand)]
+        source: import::ImportCommands,
+    },
+
+    /// Sign in to AWS IAM Identity Center (SSO) and discover its roles
+    #[command(
+        long_about = r#"Sign in to AWS IAM Identity Center (SSO) and discover its roles.
+
+EXAMPLES:
+  # Sign in using sso_start_url and sso_region from the config
+  awsr sso login
+
+  # Sign in to a specific instance
+  awsr sso login --start-url https://my-org.awsapps.com/start --region eu-west-1
+
+  # Preview, then create a role for every assigned account and permission set
+  awsr sso sync --dry-run
+  awsr sso sync --name-template '{account_name}-{role_name}'
+
+The login uses the device authorization flow: open the printed URL, confirm the
+code, and the access token is stored under ~/.aws-assume-role/sso. A still valid
+(or refreshable) token from an earlier login or from 'aws sso login' is reused.
+
+Sync never deletes roles: assignments that disappeared are marked stale."#
+    )]
+    Sso {
+        #[command(subcommand)]
+        command: sso::SsoCommands,
+    },
+
     /// Verify AWS prerequisites and permissions
     #[command(
         long_about = r#"Verify that all prerequisites are met for assuming roles.
@@ -174,6 +529,9 @@ EXAMPLES:
   # Check specific role
   awsr verify --role dev
 
+  # Check an MFA role with a code (codes are single-use, so this needs --role)
+  awsr verify --role prod --mfa-code 123456
+
 Run this command if you're having trouble assuming roles."#
     )]
     Verify {
@@ -184,9 +542,129 @@ Run this command if you're having trouble assuming roles."#
         /// Show detailed verification information
         #[arg(short, long, help = "Show detailed verification steps")]
         verbose: bool,
+
+        #[command(flatten)]
+        mfa: MfaArgs,
     },
 }
 
+/// MFA options shared by commands that call AssumeRole
+#[derive(clap::Args, Clone, Default)]
+struct MfaArgs {
+    /// Current MFA code for roles with an MFA device configured
+    #[arg(
+        long,
+        help = "6-digit MFA code (prompted for when required and omitted)"
+    )]
+    mfa_code: Option<String>,
+
+    /// Program that prints the current MFA code
+    #[arg(long, help = "Command whose output is used as the MFA code")]
+    mfa_command: Option<String>,
+}
+
+impl MfaArgs {
+    fn resolve(&self, role: &RoleConfig) -> AppResult<Option<String>> {
+        mfa::resolve_mfa_code(role, self.mfa_code.as_deref(), self.mfa_command.as_deref())
+    }
+}
+
+/// Session options shared by commands that obtain a role's credentials
+#[derive(clap::Args, Clone)]
+struct SessionArgs {
+    /// Session duration in seconds (default: 3600)
+    #[arg(
+        short,
+        long,
+        value_parser = clap::value_parser!(i32).range(900..=43200),
+        help = "Session duration in seconds (900-43200, default: role's session_duration, then 3600)"
+    )]
+    duration: Option<i32>,
+
+    /// Session name (template) overriding the configured one
+    #[arg(long, help = "Session name or template for this assumption")]
+    session_name: Option<String>,
+
+    /// Session tags added to the configured ones (repeatable)
+    #[arg(
+        long = "tag",
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value,
+        help = "Session tag for this assumption, e.g. --tag ticket=OPS-42 (repeatable, value may use placeholders)"
+    )]
+    tags: Vec<(String, String)>,
+
+    /// Inline session policy replacing the configured one
+    #[arg(
+        long,
+        value_name = "JSON|@FILE",
+        help = "Inline session policy JSON, or @path to a JSON file, that narrows the role's permissions"
+    )]
+    policy: Option<String>,
+
+    /// Managed session policies replacing the configured ones (repeatable)
+    #[arg(
+        long = "policy-arn",
+        value_name = "ARN",
+        help = "Managed policy ARN that narrows the role's permissions (repeatable, replaces the role's policy_arns)"
+    )]
+    policy_arns: Vec<String>,
+
+    /// Do not read or write the credential cache
+    #[arg(long, help = "Do not use or update cached credentials")]
+    no_cache: bool,
+
+    /// Request new credentials even if cached ones are still valid
+    #[arg(
+        long,
+        conflicts_with = "no_cache",
+        help = "Ignore cached credentials and cache the new session"
+    )]
+    refresh: bool,
+
+    #[command(flatten)]
+    mfa: MfaArgs,
+}
+
+/// Environment options for commands run with a role's credentials
+#[derive(clap::Args, Default)]
+struct EnvArgs {
+    /// Extra variables for the command (repeatable)
+    #[arg(
+        long = "env",
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value,
+        help = "Set a variable in the command's environment (repeatable)"
+    )]
+    set: Vec<(String, String)>,
+
+    /// Variables removed from the command's environment (repeatable)
+    #[arg(
+        long,
+        value_name = "VAR",
+        help = "Remove a variable from the command's environment (repeatable)"
+    )]
+    unset: Vec<String>,
+
+    /// Start from an empty environment
+    #[arg(
+        long,
+        help = "Only pass PATH, HOME, locale and terminal variables on to the command, plus the role's"
+    )]
+    clean_env: bool,
+}
+
+impl SessionArgs {
+    fn overrides(&self) -> SessionOverrides<'_> {
+        SessionOverrides {
+            session_name: self.session_name.as_deref(),
+            tags: &self.tags,
+            policy: self.policy.as_deref(),
+            policy_arns: &self.policy_arns,
+        }
+    }
+}
+
 impl Cli {
     pub async fn run() -> AppResult<()> {
         let cli = Cli::parse();
@@ -196,34 +674,99 @@ impl Cli {
             Commands::Configure {
                 name,
                 role_arn,
+                sso_role_name,
                 account_id,
                 source_profile,
                 session_duration,
+                mfa_serial,
+                external_id,
+                session_name,
+                region,
+                use_fips_endpoint,
+                use_dualstack_endpoint,
+                endpoint_url,
+                tags,
+                transitive_tag_keys,
+                session_policy,
+                policy_arns,
+                source_identity,
+                parent_role,
             } => {
                 let role = RoleConfig {
                     name: name.clone(),
-                    role_arn: role_arn.clone(),
+                    kind: if sso_role_name.is_some() {
+                        RoleKind::Sso
+                    } else {
+                        RoleKind::Sts
+                    },
+                    role_arn: role_arn.clone().unwrap_or_default(),
+                    sso_role_name: sso_role_name.clone(),
                     account_id: account_id.clone(),
                     source_profile: source_profile.clone(),
                     session_duration: *session_duration,
+                    mfa_serial: mfa_serial.clone(),
+                    external_id: external_id.clone(),
+                    session_name: session_name.clone(),
+                    source_identity: source_identity.clone(),
+                    region: region.clone(),
+                    use_fips_endpoint: use_fips_endpoint.then_some(true),
+                    use_dualstack_endpoint: use_dualstack_endpoint.then_some(true),
+                    endpoint_url: endpoint_url.clone(),
+                    tags: tags.iter().cloned().collect(),
+                    transitive_tag_keys: transitive_tag_keys.clone(),
+                    session_policy: session_policy.clone(),
+                    policy_arns: policy_arns.clone(),
+                    parent_role: parent_role.clone(),
+                    ..Default::default()
                 };
 
+                let chain = config.role_chain(&role)?;
+                config.client_settings_for(&role)?;
+
                 // Test the role configuration before saving
                 println!("🔧 Configuring role '{}'...", name);
-                let aws_client = AwsClient::new().await?;
-
-                print!("🔍 Testing role assumption... ");
-                match aws_client.test_assume_role(&role).await {
-                    Ok(true) => {
+                let aws_client = AwsClient::with_profile(
+                    config.source_profile_for(chain[0]),
+                    &config.client_settings_for(chain[0])?,
+                )
+                .await?;
+
+                let test_result = async {
+                    let mfa = MfaArgs::default();
+                    let aws_client =
+                        assume_parent_roles(aws_client, &config, &chain, &mfa, Default::default())
+                            .await?;
+                    if role.kind == RoleKind::Sso {
+                        print!("🔍 Testing SSO role credentials... ");
+                        return sso::role_credentials(&aws_client, &config, &role)
+                            .await
+                            .map(|_| RoleAccess::Allowed);
+                    }
+                    let options = build_assume_options(
+                        &aws_client,
+                        &config,
+                        &chain,
+                        chain.len() - 1,
+                        &mfa,
+                        Default::default(),
+                    )
+                    .await?;
+                    print!("🔍 Testing role assumption... ");
+                    aws_client.test_assume_role(&role, &options).await
+                }
+                .await;
+                match test_result {
+                    Ok(RoleAccess::Allowed) => {
                         println!("✅ Success!");
                         config.add_role(role);
                         config.save()?;
                         println!("✅ Role '{}' configured successfully", name);
                         println!("   Use 'awsr assume {}' to assume this role", name);
                     }
-                    Ok(false) => {
+                    Ok(RoleAccess::Denied(e)) => {
                         println!("❌ Failed!");
                         println!("⚠️  Warning: Cannot assume role '{}'", name);
+                        println!("   {}", e);
                         println!("   The role configuration will be saved, but you may not be able to assume it.");
                         println!("   Possible issues:");
                         println!("   - Role doesn't exist in account {}", account_id);
@@ -262,19 +805,40 @@ impl Cli {
 
             Commands::Assume {
                 name,
-                duration,
                 format,
+                profile_name,
                 exec,
+                session,
             } => {
                 let role = config.get_role(name).ok_or_else(|| {
                     crate::error::AppError::CliError(format!("Role '{}' not found", name))
                 })?;
 
-                let aws_client = AwsClient::new().await?;
-                let credentials = aws_client.assume_role(role, *duration).await?;
+                let credentials = role_credentials(&config, role, session).await?;
+
+                if format.as_deref() != Some("credential-process") {
+                    report_session_scope(role, &session.overrides());
+                }
 
                 if let Some(command) = exec {
-                    execute_with_credentials(&credentials, command).await?;
+                    let child = exec::ChildCommand {
+                        command: std::slice::from_ref(command),
+                        shell: true,
+                        role,
+                        region: config.region_for(role),
+                        env: &EnvArgs::default(),
+                    };
+                    let code = exec::run_with_credentials(&child, &credentials).await?;
+                    if code != 0 {
+                        std::process::exit(code);
+                    }
+                } else if format.as_deref() == Some("credentials-file") {
+                    let profile_name = profile_name.as_deref().ok_or_else(|| {
+                        crate::error::AppError::CliError(
+                            "--profile-name is required with --format credentials-file".to_string(),
+                        )
+                    })?;
+                    write_credentials_profile(&credentials, profile_name, name)?;
                 } else {
                     let format_str = format.as_deref().unwrap_or_else(|| {
                         // Better shell detection for Windows
@@ -289,7 +853,72 @@ impl Cli {
                             "export"
                         }
                     });
-                    output_credentials_for_shell(&credentials, format_str, name)?;
+                    output_credentials_for_shell(
+                        &credentials,
+                        format_str,
+                        name,
+                        config.region_for(role),
+                    )?;
+                }
+            }
+
+            Commands::Exec {
+                name,
+                shell,
+                server,
+                session,
+                env,
+                command,
+            } => {
+                let role = config.get_role(name).ok_or_else(|| {
+                    crate::error::AppError::CliError(format!("Role '{}' not found", name))
+                })?;
+
+                let credentials = role_credentials(&config, role, session).await?;
+                report_session_scope(role, &session.overrides());
+
+                let child = exec::ChildCommand {
+                    command,
+                    shell: *shell,
+                    role,
+                    region: config.region_for(role),
+                    env,
+                };
+                let code = if *server {
+                    let server =
+                        CredentialServer::bind(credentials, expiry_margin(&config)).await?;
+                    // Always request a new session: the cache would hand back the
+                    // one being replaced. An MFA code is single-use, and
+                    // prompting would compete with the command for the terminal
+                    let refresh_session = SessionArgs {
+                        refresh: true,
+                        mfa: MfaArgs {
+                            mfa_code: None,
+                            ..session.mfa.clone()
+                        },
+                        ..session.clone()
+                    };
+                    let needs_mfa = config
+                        .role_chain(role)?
+                        .iter()
+                        .any(|hop| hop.mfa_serial.is_some());
+                    let config = &config;
+                    let refresh_session = &refresh_session;
+                    exec::run_with_credential_server(&child, server, move || async move {
+                        if needs_mfa && refresh_session.mfa.mfa_command.is_none() {
+                            return Err(crate::error::AppError::CliError(format!(
+                                "Role '{}' needs MFA; pass --mfa-command to refresh its credentials",
+                                role.name
+                            )));
+                        }
+                        role_credentials(config, role, refresh_session).await
+                    })
+                    .await?
+                } else {
+                    exec::run_with_credentials(&child, &credentials).await?
+                };
+                if code != 0 {
+                    std::process::exit(code);
                 }
             }
 
@@ -301,7 +930,23 @@ impl Cli {
 
                 println!("Configured roles:");
                 for role in &config.roles {
-                    println!("- {} ({})", role.name, role.role_arn);
+                    println!("- {} ({})", role.name, role.target());
+                    if let Some(partition) = config.partition_for(role) {
+                        println!("  Partition: {}", partition);
+                    }
+                    if let Some(parent_role) = &role.parent_role {
+                        println!("  Via: {}", parent_role);
+                    }
+                    if role.stale {
+                        println!("  ⚠️  Stale: no longer assigned in IAM Identity Center");
+                    }
+                    if let Some(external_id) = &role.external_id {
+                        println!("  External ID: {}", mask_secret(external_id));
+                    }
+                    let policy = role.session_policy.as_deref();
+                    if let Some(scope) = session_scope(policy, &role.policy_arns) {
+                        println!("  Session policy: {}", scope);
+                    }
                 }
             }
 
@@ -314,8 +959,77 @@ impl Cli {
                 }
             }
 
-            Commands::Verify { role, verbose } => {
-                verify_prerequisites(&config, role.as_deref(), *verbose).await?;
+            Commands::Cache { command } => {
+                cache::run(command)?;
+            }
+
+            Commands::Import { source } => {
+                import::run(&mut config, source)?;
+            }
+
+            Commands::Sso { command } => {
+                sso::run(&mut config, command).await?;
+            }
+
+            Commands::ExportProfiles {
+                credential_process,
+                command,
+                output,
+                merge,
+            } => {
+                let mode = if *credential_process {
+                    let command = match command {
+                        Some(command) => command.clone(),
+                        None => std::env::current_exe()?.display().to_string(),
+                    };
+                    ExportMode::CredentialProcess { command }
+                } else {
+                    ExportMode::AssumeRole
+                };
+
+                if *merge {
+                    let path = profile::config_file_path()?;
+                    let existing = profile::read_optional(&path)?.unwrap_or_default();
+                    let user_profiles =
+                        profile::parse_profiles(&profile::user_owned_content(&existing)?, true);
+
+                    let (conflicting, roles): (Vec<&RoleConfig>, Vec<&RoleConfig>) = config
+                        .roles
+                        .iter()
+                        .partition(|role| user_profiles.contains_key(&role.name));
+                    for role in &conflicting {
+                        eprintln!(
+                            "⚠️  Skipping '{}': profile already defined outside the managed block",
+                            role.name
+                        );
+                    }
+
+                    let rendered = profile::render_profiles(&config, roles.iter().copied(), &mode);
+                    let merged = profile::merge_managed_block(&existing, &rendered)?;
+                    profile::write_atomic(&path, &merged, 0o600)?;
+                    println!(
+                        "✅ Exported {} profile(s) to {}",
+                        roles.len(),
+                        path.display()
+                    );
+                } else {
+                    let rendered = profile::render_profiles(&config, &config.roles, &mode);
+                    match output {
+                        Some(path) => {
+                            profile::write_atomic(path, &rendered, 0o644)?;
+                            println!(
+                                "✅ Exported {} profile(s) to {}",
+                                config.roles.len(),
+                                path.display()
+                            );
+                        }
+                        None => print!("{}", rendered),
+                    }
+                }
+            }
+
+            Commands::Verify { role, verbose, mfa } => {
+                verify_prerequisites(&config, role.as_deref(), *verbose, mfa).await?;
             }
         }
 
@@ -323,41 +1037,357 @@ impl Cli {
     }
 }
 
+/// Accept only absolute http(s) URLs for `--endpoint-url`.
+fn parse_endpoint_url(value: &str) -> Result<String, String> {
+    let rest = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"));
+    match rest {
+        Some(host) if !host.is_empty() => Ok(value.trim_end_matches('/').to_string()),
+        _ => Err("expected an http:// or https:// URL".to_string()),
+    }
+}
+
+/// Build the AssumeRole parameters shared by `assume`, `verify` and `configure`.
+///
+/// Prompts for an MFA code if the role needs one.
+async fn build_assume_options(
+    aws_client: &AwsClient,
+    config: &Config,
+    chain: &[&RoleConfig],
+    hop: usize,
+    mfa: &MfaArgs,
+    overrides: SessionOverrides<'_>,
+) -> AppResult<AssumeRoleOptions> {
+    let role = chain[hop];
+    let session_name_template = overrides.session_name.or(config.session_name_for(role));
+    let tag_templates = role.session_tags(overrides.tags);
+    // A SourceIdentity is immutable: later hops of a chain repeat the first one
+    let source_identity_template = match aws_client.source_identity() {
+        Some(_) => None,
+        None => config.source_identity_for(role),
+    };
+
+    let mut context = TemplateContext::new(&role.name);
+    let needs_principal = source_identity_template
+        .is_some_and(|template| uses_placeholder(template, "principal"))
+        && base_is_sso(config, chain, hop);
+    let needs_user_id = session_name_template
+        .into_iter()
+        .chain(source_identity_template)
+        .chain(tag_templates.iter().map(|(_, value)| value.as_str()))
+        .any(|template| uses_placeholder(template, "user_id"));
+    if needs_principal || needs_user_id {
+        let identity = aws_client.verify_current_identity().await?;
+        if needs_principal {
+            context.principal = sso_principal(&identity.arn);
+        }
+        context.user_id = Some(identity.user_id);
+    }
+
+    let session_name = match session_name_template {
+        Some(template) => {
+            let name = context.render(template, sanitize_session_name_value)?;
+            validate_role_session_name(&name)?;
+            Some(name)
+        }
+        None => None,
+    };
+    let tags = tag_templates
+        .into_iter()
+        .map(|(key, value)| Ok((key, context.render(&value, str::to_string)?)))
+        .collect::<AppResult<Vec<_>>>()?;
+    validate_session_tags(&tags, &role.transitive_tag_keys)?;
+
+    let policy = role
+        .session_policy_for(&overrides)
+        .map(resolve_session_policy)
+        .transpose()?;
+    let policy_arns = role.policy_arns_for(&overrides).to_vec();
+    validate_policy_arns(&policy_arns)?;
+
+    let source_identity = match (aws_client.source_identity(), source_identity_template) {
+        (Some(inherited), _) => Some(inherited.to_string()),
+        (None, Some(template)) => {
+            let value = context.render(template, sanitize_session_name_value)?;
+            validate_source_identity(&value)?;
+            Some(value)
+        }
+        (None, None) => None,
+    };
+
+    Ok(AssumeRoleOptions {
+        mfa_token: mfa.resolve(role)?,
+        session_name,
+        tags,
+        transitive_tag_keys: role.transitive_tag_keys.clone(),
+        policy,
+        policy_arns,
+        source_identity,
+        ..Default::default()
+    })
+}
+
+/// Whether the credentials used for hop `hop` of `chain` are an IAM Identity
+/// Center session: an SSO role right before it, or an SSO source profile.
+fn base_is_sso(config: &Config, chain: &[&RoleConfig], hop: usize) -> bool {
+    if hop > 0 {
+        return chain[hop - 1].kind == RoleKind::Sso;
+    }
+    let Some(profile_name) = config.source_profile_for(chain[0]) else {
+        return false;
+    };
+    profile::Profiles::load()
+        .ok()
+        .and_then(|profiles| {
+            profiles.get(profile_name).map(|properties| {
+                properties.contains_key("sso_start_url") || properties.contains_key("sso_session")
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// The IAM Identity Center user name in the ARN of an SSO role session, e.g.
+/// `alice` in `arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_1a2b/alice`.
+fn sso_principal(identity_arn: &str) -> Option<String> {
+    let arn = Arn::parse(identity_arn).ok()?;
+    let rest = arn.resource.strip_prefix("assumed-role/AWSReservedSSO_")?;
+    rest.split_once('/').map(|(_, session)| session.to_string())
+}
+
+/// Summary of the session policies narrowing a session, e.g.
+/// `inline policy (@readonly.json), arn:aws:iam::aws:policy/ReadOnlyAccess`.
+fn session_scope(policy: Option<&str>, policy_arns: &[String]) -> Option<String> {
+    let mut parts = Vec::new();
+    match policy {
+        Some(source) if source.starts_with('@') => {
+            parts.push(format!("inline policy ({})", source))
+        }
+        Some(_) => parts.push("inline policy".to_string()),
+        None => {}
+    }
+    parts.extend(policy_arns.iter().cloned());
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Parse a `KEY=VALUE` argument such as `--tag` or `--env`.
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected KEY=VALUE".to_string()),
+    }
+}
+
+/// AWS caps sessions obtained through role chaining at one hour.
+const CHAINED_SESSION_MAX_SECONDS: i32 = 3600;
+
+/// Assume hop `hop` of `chain` with `client`, which must hold the previous
+/// hop's credentials (or the root's source profile for hop 0).
+async fn assume_chain_hop(
+    client: &AwsClient,
+    config: &Config,
+    chain: &[&RoleConfig],
+    hop: usize,
+    duration: Option<i32>,
+    mfa: &MfaArgs,
+    overrides: SessionOverrides<'_>,
+) -> AppResult<Credentials> {
+    let role = chain[hop];
+    let result = async {
+        if role.kind == RoleKind::Sso {
+            return sso::role_credentials(client, config, role).await;
+        }
+
+        let mut options =
+            build_assume_options(client, config, chain, hop, mfa, overrides).await?;
+        options.duration_seconds = match duration {
+            Some(seconds) if hop > 0 && seconds > CHAINED_SESSION_MAX_SECONDS => {
+                eprintln!(
+                    "⚠️  Role '{}' is reached through a role chain; AWS limits chained sessions to {}s (requested {}s)",
+                    role.name, CHAINED_SESSION_MAX_SECONDS, seconds
+                );
+                Some(CHAINED_SESSION_MAX_SECONDS)
+            }
+            other => other,
+        };
+        client.assume_role(role, &options).await
+    }
+    .await;
+
+    result.map_err(|e| {
+        if chain.len() == 1 {
+            return e;
+        }
+        let from = match hop {
+            0 => match config.source_profile_for(chain[0]) {
+                Some(profile) => format!("profile '{}'", profile),
+                None => "default credentials".to_string(),
+            },
+            _ => format!("'{}'", chain[hop - 1].name),
+        };
+        e.with_context(&format!(
+            "Role chain link {}/{} ({} -> '{}') failed",
+            hop + 1,
+            chain.len(),
+            from,
+            role.name
+        ))
+    })
+}
+
+/// Walk every intermediate role of `chain`, starting from `client`, and return
+/// a client holding the credentials of the hop just before the target role.
+async fn assume_parent_roles(
+    mut client: AwsClient,
+    config: &Config,
+    chain: &[&RoleConfig],
+    mfa: &MfaArgs,
+    overrides: SessionOverrides<'_>,
+) -> AppResult<AwsClient> {
+    let overrides = overrides.for_parent_roles();
+    for hop in 0..chain.len() - 1 {
+        let credentials =
+            assume_chain_hop(&client, config, chain, hop, None, mfa, overrides).await?;
+        client = client
+            .with_credentials(&credentials, &config.client_settings_for(chain[hop + 1])?)
+            .await;
+    }
+    Ok(client)
+}
+
 fn output_credentials_for_shell(
     credentials: &Credentials,
     format: &str,
     role_name: &str,
+    region: Option<&str>,
 ) -> AppResult<()> {
     match format {
         "json" => {
-            println!("{{");
-            println!("  \"AccessKeyId\": \"{}\",", credentials.access_key_id);
-            println!(
-                "  \"SecretAccessKey\": \"{}\",",
-                credentials.secret_access_key
-            );
-            if let Some(token) = &credentials.session_token {
-                println!("  \"SessionToken\": \"{}\",", token);
-            }
-            if let Some(expiration) = &credentials.expiration {
-                println!(
-                    "  \"Expiration\": \"{}\"",
-                    expiration
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs()
-                );
-            }
-            println!("}}");
+            let json =
+                serde_json::to_string_pretty(&json_output(credentials, region)).map_err(|e| {
+                    crate::error::AppError::CliError(format!(
+                        "Failed to serialize credentials: {}",
+                        e
+                    ))
+                })?;
+            println!("{}", json);
+        }
+        "credential-process" => {
+            let json =
+                serde_json::to_string(&credential_process_output(credentials)).map_err(|e| {
+                    crate::error::AppError::CliError(format!(
+                        "Failed to serialize credentials: {}",
+                        e
+                    ))
+                })?;
+            println!("{}", json);
         }
         _ => {
             // Default export format - optimized for the target shell
-            output_shell_exports(credentials, role_name)?;
+            output_shell_exports(credentials, role_name, region)?;
         }
     }
     Ok(())
 }
 
+/// Store credentials as a named profile in the shared credentials file.
+///
+/// Other profiles, comments and the file's permissions are left as they are.
+fn write_credentials_profile(
+    credentials: &Credentials,
+    profile_name: &str,
+    role_name: &str,
+) -> AppResult<()> {
+    if profile_name.trim().is_empty()
+        || profile_name.contains(|c: char| c == '[' || c == ']' || c.is_control())
+    {
+        return Err(crate::error::AppError::CliError(format!(
+            "Invalid profile name '{}'",
+            profile_name
+        )));
+    }
+
+    let mut body = Vec::new();
+    if let Some(expiration) = credentials.expiration {
+        body.push(format!(
+            "# Role '{}' expires {} (written by aws-assume-role)",
+            role_name,
+            format_timestamp(expiration)
+        ));
+    }
+    body.push(format!("aws_access_key_id = {}", credentials.access_key_id));
+    body.push(format!(
+        "aws_secret_access_key = {}",
+        credentials.secret_access_key
+    ));
+    if let Some(token) = &credentials.session_token {
+        body.push(format!("aws_session_token = {}", token));
+    }
+
+    let path = profile::credentials_file_path()?;
+    let existing = profile::read_optional(&path)?.unwrap_or_default();
+    let updated = profile::upsert_section(&existing, profile_name, &body);
+    profile::write_atomic(&path, &updated, 0o600)?;
+
+    // stderr, so an eval'ing shell wrapper has nothing to execute
+    eprintln!(
+        "✅ Credentials for role '{}' written to profile '{}' in {}",
+        role_name,
+        profile_name,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Credentials as printed by `--format json`.
+fn json_output(credentials: &Credentials, region: Option<&str>) -> serde_json::Value {
+    let mut output = serde_json::json!({
+        "AccessKeyId": credentials.access_key_id,
+        "SecretAccessKey": credentials.secret_access_key,
+    });
+    if let Some(token) = &credentials.session_token {
+        output["SessionToken"] = token.clone().into();
+    }
+    if let Some(region) = region {
+        output["Region"] = region.into();
+    }
+    if let Some(expiration) = credentials.expiration {
+        let seconds = expiration
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        output["Expiration"] = seconds.to_string().into();
+    }
+    output
+}
+
+/// Credentials in the `credential_process` schema understood by the AWS CLI and SDKs.
+fn credential_process_output(credentials: &Credentials) -> serde_json::Value {
+    let mut output = serde_json::json!({
+        "Version": 1,
+        "AccessKeyId": credentials.access_key_id,
+        "SecretAccessKey": credentials.secret_access_key,
+    });
+    if let Some(token) = &credentials.session_token {
+        output["SessionToken"] = token.clone().into();
+    }
+    if let Some(expiration) = credentials.expiration {
+        output["Expiration"] = format_timestamp(expiration).into();
+    }
+    output
+}
+
+/// Mask a sensitive value for display, keeping only the last few characters
+/// of long values so they can still be told apart.
+fn mask_secret(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        return "****".to_string();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{}", tail)
+}
+
 /// Detect if we're running in Git Bash or similar Unix-like environment on Windows
 fn is_git_bash_or_unix_like() -> bool {
     use std::env;
@@ -394,7 +1424,11 @@ fn is_git_bash_or_unix_like() -> bool {
 }
 
 #[cfg(target_os = "windows")]
-fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult<()> {
+fn output_shell_exports(
+    credentials: &Credentials,
+    role_name: &str,
+    region: Option<&str>,
+) -> AppResult<()> {
     use std::env;
 
     // Check if we're in Git Bash or similar Unix-like environment
@@ -408,6 +1442,10 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
         if let Some(token) = &credentials.session_token {
             println!("export AWS_SESSION_TOKEN=\"{}\"", token);
         }
+        if let Some(region) = region {
+            println!("export AWS_REGION=\"{}\"", region);
+            println!("export AWS_DEFAULT_REGION=\"{}\"", region);
+        }
         println!("echo \"✅ Assumed role: {}\"", role_name);
     } else if env::var("PSModulePath").is_ok() {
         // PowerShell format
@@ -419,6 +1457,10 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
         if let Some(token) = &credentials.session_token {
             println!("$env:AWS_SESSION_TOKEN = \"{}\"", token);
         }
+        if let Some(region) = region {
+            println!("$env:AWS_REGION = \"{}\"", region);
+            println!("$env:AWS_DEFAULT_REGION = \"{}\"", region);
+        }
         println!(
             "Write-Host \"✅ Assumed role: {}\" -ForegroundColor Green",
             role_name
@@ -433,6 +1475,10 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
         if let Some(token) = &credentials.session_token {
             println!("set AWS_SESSION_TOKEN={}", token);
         }
+        if let Some(region) = region {
+            println!("set AWS_REGION={}", region);
+            println!("set AWS_DEFAULT_REGION={}", region);
+        }
         println!("echo ✅ Assumed role: {}", role_name);
     }
 
@@ -440,7 +1486,11 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
 }
 
 #[cfg(not(target_os = "windows"))]
-fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult<()> {
+fn output_shell_exports(
+    credentials: &Credentials,
+    role_name: &str,
+    region: Option<&str>,
+) -> AppResult<()> {
     use std::env;
 
     // Check for Fish shell
@@ -457,6 +1507,10 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
             if let Some(token) = &credentials.session_token {
                 println!("set -gx AWS_SESSION_TOKEN \"{}\"", token);
             }
+            if let Some(region) = region {
+                println!("set -gx AWS_REGION \"{}\"", region);
+                println!("set -gx AWS_DEFAULT_REGION \"{}\"", region);
+            }
             println!("echo \"✅ Assumed role: {}\"", role_name);
             return Ok(());
         }
@@ -471,50 +1525,102 @@ fn output_shell_exports(credentials: &Credentials, role_name: &str) -> AppResult
     if let Some(token) = &credentials.session_token {
         println!("export AWS_SESSION_TOKEN=\"{}\"", token);
     }
+    if let Some(region) = region {
+        println!("export AWS_REGION=\"{}\"", region);
+        println!("export AWS_DEFAULT_REGION=\"{}\"", region);
+    }
     println!("echo \"✅ Assumed role: {}\"", role_name);
 
     Ok(())
 }
 
-async fn execute_with_credentials(credentials: &Credentials, command: &str) -> AppResult<()> {
-    use std::process::Command;
-
-    // Parse the command string into command and args
-    let mut parts = command.split_whitespace();
-    let cmd = parts
-        .next()
-        .ok_or_else(|| crate::error::AppError::CliError("Empty command".to_string()))?;
-    let args: Vec<&str> = parts.collect();
-
-    // Create the command with environment variables
-    let mut child = Command::new(cmd);
-    child.args(&args);
-    child.env("AWS_ACCESS_KEY_ID", &credentials.access_key_id);
-    child.env("AWS_SECRET_ACCESS_KEY", &credentials.secret_access_key);
+/// Credentials for `role`, from the cache when possible, otherwise by
+/// assuming each hop of its chain.
+async fn role_credentials(
+    config: &Config,
+    role: &RoleConfig,
+    session: &SessionArgs,
+) -> AppResult<Credentials> {
+    let chain = config.role_chain(role)?;
+    if role.kind == RoleKind::Sso && session.duration.is_some() {
+        eprintln!(
+            "⚠️  --duration is ignored for SSO role '{}'; its permission set defines the session length",
+            role.name
+        );
+    }
+    if role.kind == RoleKind::Sso
+        && (!session.tags.is_empty() || session.policy.is_some() || !session.policy_arns.is_empty())
+    {
+        eprintln!(
+            "⚠️  --tag, --policy and --policy-arn are ignored for SSO role '{}'; they need sts:AssumeRole",
+            role.name
+        );
+    }
+    let duration = config.session_duration_for(role, session.duration)?;
+    let overrides = session.overrides();
+    let cache = CredentialCache::open()?;
+    let cache_key = CacheKey::new(config, role, duration, &overrides);
+    let margin = expiry_margin(config);
+
+    if !session.no_cache && !session.refresh {
+        if let Some(credentials) = cache.get(&cache_key, margin) {
+            return Ok(credentials);
+        }
+    }
 
-    if let Some(token) = &credentials.session_token {
-        child.env("AWS_SESSION_TOKEN", token);
+    let aws_client = assume_parent_roles(
+        AwsClient::with_profile(
+            config.source_profile_for(chain[0]),
+            &config.client_settings_for(chain[0])?,
+        )
+        .await?,
+        config,
+        &chain,
+        &session.mfa,
+        overrides,
+    )
+    .await?;
+    let credentials = assume_chain_hop(
+        &aws_client,
+        config,
+        &chain,
+        chain.len() - 1,
+        duration,
+        &session.mfa,
+        overrides,
+    )
+    .await?;
+
+    if !session.no_cache {
+        if let Err(e) = cache.put(cache_key, &credentials) {
+            eprintln!("⚠️  Could not cache credentials: {}", e);
+        }
     }
+    Ok(credentials)
+}
 
-    // Execute the command
-    let status = child.status().map_err(|e| {
-        crate::error::AppError::CliError(format!("Failed to execute command: {}", e))
-    })?;
+/// How long before expiry credentials are considered stale.
+fn expiry_margin(config: &Config) -> Duration {
+    Duration::from_secs(
+        config
+            .cache_expiry_margin_seconds
+            .unwrap_or(DEFAULT_EXPIRY_MARGIN_SECONDS),
+    )
+}
 
-    if !status.success() {
-        return Err(crate::error::AppError::CliError(format!(
-            "Command failed with exit code: {:?}",
-            status.code()
-        )));
+/// Tell the user on stderr when a session policy narrows the role.
+fn report_session_scope(role: &RoleConfig, overrides: &SessionOverrides) {
+    let policy = role.session_policy_for(overrides);
+    if let Some(scope) = session_scope(policy, role.policy_arns_for(overrides)) {
+        eprintln!("🔒 Session narrowed by {}", scope);
     }
-
-    Ok(())
 }
 
 async fn verify_prerequisites(
     config: &Config,
     specific_role: Option<&str>,
     verbose: bool,
+    mfa: &MfaArgs,
 ) -> AppResult<()> {
     println!("🔍 Verifying AWS prerequisites...\n");
 
@@ -536,7 +1642,17 @@ async fn verify_prerequisites(
     if verbose {
         println!("\nChecking AWS credentials...");
     }
-    match AwsClient::new().await {
+    let default_profile = config.default_profile.as_deref();
+    let default_settings = config.default_client_settings();
+    if verbose {
+        if let Some(profile) = default_profile {
+            println!("   - Using default profile '{}'", profile);
+        }
+        if let Some(endpoint_url) = &default_settings.endpoint_url {
+            println!("   - Using endpoint {}", endpoint_url);
+        }
+    }
+    match AwsClient::with_profile(default_profile, &default_settings).await {
         Ok(aws_client) => {
             println!("✅ AWS SDK initialized successfully");
             match aws_client.verify_current_identity().await {
@@ -546,6 +1662,9 @@ async fn verify_prerequisites(
                         println!("   - Account: {}", identity.account);
                         println!("   - ARN: {}", identity.arn);
                         println!("   - User ID: {}", identity.user_id);
+                        if let Ok(arn) = Arn::parse(&identity.arn) {
+                            println!("   - Partition: {}", arn.partition);
+                        }
                     }
                 }
                 Err(e) => {
@@ -573,16 +1692,141 @@ async fn verify_prerequisites(
                     config.roles.iter().collect()
                 };
 
+                // An MFA code is single-use, so it only applies to one named role;
+                // otherwise each MFA role prompts (or runs --mfa-command) in turn
+                let mut mfa = mfa.clone();
+                if specific_role.is_none() && mfa.mfa_code.take().is_some() {
+                    println!(
+                        "⚠️  --mfa-code applies only with --role; prompting for each MFA role"
+                    );
+                }
+                let mfa = &mfa;
+
                 for role in roles_to_check {
                     if verbose {
                         println!("   - Testing role assumption for '{}'...", role.name);
                     }
-                    match aws_client.test_assume_role(role).await {
-                        Ok(true) => {
+                    let chain = match config.role_chain(role) {
+                        Ok(chain) => chain,
+                        Err(e) => {
+                            println!("   ❌ Cannot assume role '{}': {}", role.name, e);
+                            all_checks_passed = false;
+                            continue;
+                        }
+                    };
+                    let settings = match config.client_settings_for(chain[0]) {
+                        Ok(settings) => settings,
+                        Err(e) => {
+                            println!("   ❌ Cannot assume role '{}': {}", role.name, e);
+                            all_checks_passed = false;
+                            continue;
+                        }
+                    };
+                    let source_profile = config.source_profile_for(chain[0]);
+                    if verbose {
+                        if let Some(profile) = source_profile {
+                            println!("     Source profile: {}", profile);
+                        }
+                        if let Some(partition) = config.partition_for(role) {
+                            println!("     Partition: {}", partition);
+                        }
+                        if let Some(region) = config.region_for(role) {
+                            println!("     Region: {}", region);
+                        }
+                        if let Some(endpoint_url) = config.endpoint_url_for(Some(role)) {
+                            println!("     Endpoint: {}", endpoint_url);
+                        }
+                        if !role.tags.is_empty() {
+                            let tags: Vec<String> = role
+                                .tags
+                                .iter()
+                                .map(|(key, value)| format!("{}={}", key, value))
+                                .collect();
+                            println!("     Session tags: {}", tags.join(", "));
+                        }
+                        if !role.transitive_tag_keys.is_empty() {
+                            println!(
+                                "     Transitive tag keys: {}",
+                                role.transitive_tag_keys.join(", ")
+                            );
+                        }
+                        let policy = role.session_policy.as_deref();
+                        if let Some(scope) = session_scope(policy, &role.policy_arns) {
+                            println!("     Session policy: {}", scope);
+                        }
+                        if chain.len() > 1 {
+                            let names: Vec<&str> = chain.iter().map(|r| r.name.as_str()).collect();
+                            println!("     Role chain: {}", names.join(" -> "));
+                        }
+                    }
+
+                    // Roles with their own source profile or client settings need a client built from them
+                    let client =
+                        if source_profile == default_profile && settings == default_settings {
+                            Ok(aws_client.clone())
+                        } else {
+                            AwsClient::with_profile(source_profile, &settings).await
+                        };
+                    let client = match client {
+                        Ok(client) => {
+                            assume_parent_roles(client, config, &chain, mfa, Default::default())
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let client = match client {
+                        Ok(client) => client,
+                        Err(e) => {
+                            println!("   ❌ Cannot assume role '{}': {}", role.name, e);
+                            all_checks_passed = false;
+                            continue;
+                        }
+                    };
+
+                    if role.kind == RoleKind::Sso {
+                        match sso::role_credentials(&client, config, role).await {
+                            Ok(_) => println!("   ✅ Can get SSO credentials for '{}'", role.name),
+                            Err(e) => {
+                                println!(
+                                    "   ❌ Cannot get SSO credentials for '{}': {}",
+                                    role.name, e
+                                );
+                                all_checks_passed = false;
+                            }
+                        }
+                        continue;
+                    }
+
+                    let options = match build_assume_options(
+                        &client,
+                        config,
+                        &chain,
+                        chain.len() - 1,
+                        mfa,
+                        Default::default(),
+                    )
+                    .await
+                    {
+                        Ok(options) => options,
+                        Err(e) => {
+                            println!("   ⚠️  Could not test role '{}': {}", role.name, e);
+                            all_checks_passed = false;
+                            continue;
+                        }
+                    };
+                    if verbose {
+                        if let Some(source_identity) = &options.source_identity {
+                            println!("     Source identity: {}", source_identity);
+                        }
+                    }
+
+                    match client.test_assume_role(role, &options).await {
+                        Ok(RoleAccess::Allowed) => {
                             println!("   ✅ Can assume role '{}'", role.name);
                         }
-                        Ok(false) => {
+                        Ok(RoleAccess::Denied(e)) => {
                             println!("   ❌ Cannot assume role '{}'", role.name);
+                            println!("      {}", e);
                             all_checks_passed = false;
                         }
                         Err(e) => {
@@ -610,3 +1854,108 @@ async fn verify_prerequisites(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_output_without_expiration() {
+        let credentials = Credentials {
+            access_key_id: "ASIAEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expiration: None,
+            assumed_role_arn: None,
+            source_identity: None,
+        };
+
+        let printed =
+            serde_json::to_string_pretty(&json_output(&credentials, Some("eu-west-1"))).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(parsed["Region"], "eu-west-1");
+        assert_eq!(parsed["SessionToken"], "token");
+        assert!(parsed.get("Expiration").is_none());
+    }
+
+    #[test]
+    fn test_credential_process_output() {
+        let credentials = Credentials {
+            access_key_id: "ASIAEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expiration: Some(std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            assumed_role_arn: None,
+            source_identity: None,
+        };
+
+        let output = credential_process_output(&credentials);
+        assert_eq!(output["Version"], 1);
+        assert_eq!(output["AccessKeyId"], "ASIAEXAMPLE");
+        assert_eq!(output["SecretAccessKey"], "secret");
+        assert_eq!(output["SessionToken"], "token");
+        assert_eq!(output["Expiration"], "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn test_mask_secret() {
+        assert_eq!(mask_secret("short"), "****");
+        assert_eq!(mask_secret("12345678"), "****");
+        assert_eq!(mask_secret("external-id-1234"), "****1234");
+        assert!(!mask_secret("external-id-1234").contains("external"));
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(
+            parse_key_value("ticket=OPS-42").unwrap(),
+            ("ticket".to_string(), "OPS-42".to_string())
+        );
+        assert_eq!(
+            parse_key_value("filter=a=b").unwrap(),
+            ("filter".to_string(), "a=b".to_string())
+        );
+        assert_eq!(parse_key_value("owner=").unwrap().1, "");
+        assert!(parse_key_value("ticket").is_err());
+        assert!(parse_key_value("=x").is_err());
+    }
+
+    #[test]
+    fn test_session_scope() {
+        assert_eq!(session_scope(None, &[]), None);
+        assert_eq!(
+            session_scope(
+                Some("@readonly.json"),
+                &["arn:aws:iam::aws:policy/ReadOnlyAccess".to_string()]
+            )
+            .unwrap(),
+            "inline policy (@readonly.json), arn:aws:iam::aws:policy/ReadOnlyAccess"
+        );
+        assert_eq!(session_scope(Some("{}"), &[]).unwrap(), "inline policy");
+    }
+
+    #[test]
+    fn test_sso_principal() {
+        assert_eq!(
+            sso_principal("arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_1a2b3c/alice@example.com")
+                .as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(
+            sso_principal("arn:aws:sts::123456789012:assumed-role/Dev/session"),
+            None
+        );
+        assert_eq!(sso_principal("arn:aws:iam::123456789012:user/alice"), None);
+    }
+
+    #[test]
+    fn test_parse_endpoint_url() {
+        assert_eq!(
+            parse_endpoint_url("http://localhost:4566/").unwrap(),
+            "http://localhost:4566"
+        );
+        assert!(parse_endpoint_url("https://sts.internal.example").is_ok());
+        assert!(parse_endpoint_url("localhost:4566").is_err());
+        assert!(parse_endpoint_url("http://").is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use crate::aws::sso::{cached_token, SsoLogin, SsoToken, SsoTokenStore};
+use crate::aws::{format_timestamp, AwsClient, Credentials, SsoAccountRole};
+use crate::config::template::{render_with, unknown_placeholder};
+use crate::config::{Config, RoleConfig, RoleKind};
+use crate::error::{AppError, AppResult};
+use clap::Subcommand;
+
+const DEFAULT_NAME_TEMPLATE: &str = "{account_name}-{role_name}";
+
+#[derive(Subcommand)]
+pub enum SsoCommands {
+    /// Sign in to IAM Identity Center and store the access token
+    Login {
+        /// SSO start URL (default: sso_start_url from the config)
+        #[arg(long, help = "SSO start URL, e.g. https://my-org.awsapps.com/start")]
+        start_url: Option<String>,
+
+        /// Region of the IAM Identity Center instance (default: sso_region from the config)
+        #[arg(long, help = "Region of the IAM Identity Center instance")]
+        region: Option<String>,
+
+        /// Sign in again even if a cached token is still valid
+        #[arg(
+            long,
+            help = "Ignore tokens cached by awsr or 'aws sso login' and sign in again"
+        )]
+        force: bool,
+    },
+
+    /// Create or update SSO roles for every assigned account and permission set
+    Sync {
+        /// Name template for new roles
+        #[arg(
+            long,
+            help = "Role name template (default: sso_name_template from the config, then '{account_name}-{role_name}'; placeholders: {account_name}, {account_id}, {role_name})"
+        )]
+        name_template: Option<String>,
+
+        /// Show what would change without saving
+        #[arg(long, help = "Show the changes without saving")]
+        dry_run: bool,
+    },
+}
+
+/// What syncing the discovered assignments would do to the configuration.
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    pub added: Vec<RoleConfig>,
+    pub unchanged: Vec<String>,
+    /// Stale roles whose assignment exists again
+    pub restored: Vec<String>,
+    /// SSO roles whose assignment no longer exists
+    pub stale: Vec<String>,
+    /// Assignments whose generated name is taken by another role
+    pub conflicts: Vec<RoleConfig>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.restored.is_empty() && self.stale.is_empty()
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        for role in &self.added {
+            config.add_role(role.clone());
+        }
+        for role in config.roles.iter_mut() {
+            if self.restored.contains(&role.name) {
+                role.stale = false;
+            } else if self.stale.contains(&role.name) {
+                role.stale = true;
+            }
+        }
+    }
+}
+
+/// Match `discovered` assignments against the configured SSO roles.
+///
+/// Existing roles are matched by account and permission set, so renamed roles
+/// are kept; only new assignments get a name from `name_template`.
+pub fn plan_sync(
+    config: &Config,
+    discovered: &[SsoAccountRole],
+    name_template: &str,
+) -> AppResult<SyncPlan> {
+    let mut plan = SyncPlan::default();
+    let same_assignment = |role: &RoleConfig, assignment: &SsoAccountRole| {
+        role.kind == RoleKind::Sso
+            && role.account_id == assignment.account_id
+            && role.sso_role_name.as_deref() == Some(assignment.role_name.as_str())
+    };
+
+    for assignment in discovered {
+        if let Some(existing) = config.roles.iter().find(|r| same_assignment(r, assignment)) {
+            if existing.stale {
+                plan.restored.push(existing.name.clone());
+            } else {
+                plan.unchanged.push(existing.name.clone());
+            }
+            continue;
+        }
+
+        let name = render_with(
+            name_template,
+            |placeholder| match placeholder {
+                "account_name" => Ok(assignment.account_name.clone()),
+                "account_id" => Ok(assignment.account_id.clone()),
+                "role_name" => Ok(assignment.role_name.clone()),
+                other => Err(unknown_placeholder(other)),
+            },
+            sanitize_role_name_value,
+        )?;
+        let role = RoleConfig {
+            name,
+            kind: RoleKind::Sso,
+            account_id: assignment.account_id.clone(),
+            sso_role_name: Some(assignment.role_name.clone()),
+            ..Default::default()
+        };
+
+        let name_taken =
+            config.get_role(&role.name).is_some() || plan.added.iter().any(|r| r.name == role.name);
+        if name_taken {
+            plan.conflicts.push(role);
+        } else {
+            plan.added.push(role);
+        }
+    }
+
+    plan.stale = config
+        .roles
+        .iter()
+        .filter(|r| r.kind == RoleKind::Sso && !r.stale)
+        .filter(|r| !discovered.iter().any(|a| same_assignment(r, a)))
+        .map(|r| r.name.clone())
+        .collect();
+
+    Ok(plan)
+}
+
+/// Account names may contain spaces and punctuation; keep role names shell-friendly.
+fn sanitize_role_name_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// A valid (possibly refreshed) cached token for the Identity Center instance.
+async fn signed_in_token(start_url: &str, region: &str) -> AppResult<SsoToken> {
+    let login = SsoLogin::new(region).await;
+    cached_token(&SsoTokenStore::all()?, start_url, &login)
+        .await
+        .ok_or_else(|| {
+            AppError::AwsError(format!(
+                "No valid SSO token for {}: run 'awsr sso login' (or 'aws sso login')",
+                start_url
+            ))
+        })
+}
+
+/// Fetch credentials for an SSO role with the cached Identity Center token.
+pub async fn role_credentials(
+    client: &AwsClient,
+    config: &Config,
+    role: &RoleConfig,
+) -> AppResult<Credentials> {
+    let (start_url, region) = match (&config.sso_start_url, &config.sso_region) {
+        (Some(start_url), Some(region)) => (start_url, region),
+        _ => {
+            return Err(AppError::ConfigError(format!(
+                "SSO role '{}' needs sso_start_url and sso_region in the config",
+                role.name
+            )))
+        }
+    };
+    let role_name = role.sso_role_name.as_deref().ok_or_else(|| {
+        AppError::ConfigError(format!("SSO role '{}' has no sso_role_name", role.name))
+    })?;
+
+    let token = signed_in_token(start_url, region).await?;
+    client
+        .get_sso_credentials(&role.account_id, role_name, &token)
+        .await
+}
+
+pub async fn run(config: &mut Config, command: &SsoCommands) -> AppResult<()> {
+    match command {
+        SsoCommands::Login {
+            start_url,
+            region,
+            force,
+        } => {
+            let start_url = start_url
+                .as_deref()
+                .or(config.sso_start_url.as_deref())
+                .ok_or_else(|| {
+                    AppError::CliError(
+                        "No SSO start URL: pass --start-url or set sso_start_url in the config"
+                            .to_string(),
+                    )
+                })?;
+            let region = region
+                .as_deref()
+                .or(config.sso_region.as_deref())
+                .ok_or_else(|| {
+                    AppError::CliError(
+                        "No SSO region: pass --region or set sso_region in the config".to_string(),
+                    )
+                })?;
+
+            let login = SsoLogin::new(region).await;
+            if !*force {
+                if let Some(token) = cached_token(&SsoTokenStore::all()?, start_url, &login).await {
+                    println!("✅ Already signed in to {}", start_url);
+                    if let Some(expiration) = token.expiration() {
+                        println!("   Token valid until {}", format_timestamp(expiration));
+                    }
+                    println!("   Use --force to sign in again");
+                    return Ok(());
+                }
+            }
+
+            let store = SsoTokenStore::open()?;
+            let previous = store.get(start_url);
+
+            println!("🔐 Signing in to {}", start_url);
+            let token = login
+                .login(start_url, region, previous.as_ref(), |authorization| {
+                    println!();
+                    println!("   Open this URL in a browser and approve the request:");
+                    println!(
+                        "   {}",
+                        authorization
+                            .verification_uri_complete
+                            .as_deref()
+                            .unwrap_or(&authorization.verification_uri)
+                    );
+                    println!("   Code: {}", authorization.user_code);
+                    println!();
+                    println!(
+                        "⏳ Waiting for approval (expires in {}s)...",
+                        authorization.expires_in.as_secs()
+                    );
+                })
+                .await?;
+
+            let path = store.put(&token)?;
+            println!("✅ Signed in to {}", start_url);
+            if let Some(expiration) = token.expiration() {
+                println!("   Token valid until {}", format_timestamp(expiration));
+            }
+            println!("   Stored in {}", path.display());
+        }
+
+        SsoCommands::Sync {
+            name_template,
+            dry_run,
+        } => {
+            let (start_url, region) = match (&config.sso_start_url, &config.sso_region) {
+                (Some(start_url), Some(region)) => (start_url.clone(), region.clone()),
+                _ => {
+                    return Err(AppError::ConfigError(
+                        "Set sso_start_url and sso_region in the config before syncing".to_string(),
+                    ))
+                }
+            };
+            let name_template = name_template
+                .as_deref()
+                .or(config.sso_name_template.as_deref())
+                .unwrap_or(DEFAULT_NAME_TEMPLATE)
+                .to_string();
+
+            let token = signed_in_token(&start_url, &region).await?;
+            println!(
+                "🔍 Discovering accounts and permission sets in {}",
+                start_url
+            );
+            let discovered = AwsClient::with_profile(None, &config.default_client_settings())
+                .await?
+                .list_sso_account_roles(&token)
+                .await?;
+            let plan = plan_sync(config, &discovered, &name_template)?;
+
+            let (add, mark) = if *dry_run {
+                ("Would add", "would be marked")
+            } else {
+                ("Added", "marked")
+            };
+            for role in &plan.added {
+                println!("➕ {} '{}' ({})", add, role.name, role.target());
+            }
+            for name in &plan.restored {
+                println!("♻️  '{}' is assigned again and no longer stale", name);
+            }
+            for name in &plan.stale {
+                println!("➖ '{}' is no longer assigned; {} stale", name, mark);
+            }
+            for role in &plan.conflicts {
+                println!(
+                    "⚠️  Conflict: name '{}' for {} is already used by another role (use --name-template)",
+                    role.name,
+                    role.target()
+                );
+            }
+            println!(
+                "\n{} assignment(s) found: {} new, {} unchanged, {} restored, {} stale, {} conflicting",
+                discovered.len(),
+                plan.added.len(),
+                plan.unchanged.len(),
+                plan.restored.len(),
+                plan.stale.len(),
+                plan.conflicts.len()
+            );
+
+            if *dry_run {
+                println!("Dry run: configuration not changed");
+                return Ok(());
+            }
+            if !plan.is_empty() {
+                plan.apply(config);
+                config.save()?;
+            }
+            println!("✅ SSO roles synced");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(account_id: &str, account_name: &str, role_name: &str) -> SsoAccountRole {
+        SsoAccountRole {
+            account_id: account_id.to_string(),
+            account_name: account_name.to_string(),
+            role_name: role_name.to_string(),
+        }
+    }
+
+    fn sso_role(name: &str, account_id: &str, role_name: &str) -> RoleConfig {
+        RoleConfig {
+            name: name.to_string(),
+            kind: RoleKind::Sso,
+            account_id: account_id.to_string(),
+            sso_role_name: Some(role_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_sync() {
+        let mut config = Config::new();
+        // Renamed by the user; still matched by account and permission set
+        config.add_role(sso_role("prod-admin", "111111111111", "Admin"));
+        config.add_role(sso_role("old", "333333333333", "Admin"));
+        let mut restored = sso_role("dev-ro", "222222222222", "ReadOnly");
+        restored.stale = true;
+        config.add_role(restored);
+        config.add_role(RoleConfig {
+            name: "Sandbox-Admin".to_string(),
+            role_arn: "arn:aws:iam::444444444444:role/Admin".to_string(),
+            ..Default::default()
+        });
+
+        let discovered = vec![
+            assignment("111111111111", "Production", "Admin"),
+            assignment("222222222222", "Dev", "ReadOnly"),
+            assignment("555555555555", "Shared Services", "PowerUser"),
+            assignment("444444444444", "Sandbox", "Admin"),
+        ];
+        let plan = plan_sync(&config, &discovered, DEFAULT_NAME_TEMPLATE).unwrap();
+
+        assert_eq!(plan.unchanged, vec!["prod-admin"]);
+        assert_eq!(plan.restored, vec!["dev-ro"]);
+        assert_eq!(plan.stale, vec!["old"]);
+        assert_eq!(plan.added.len(), 1);
+        assert_eq!(plan.added[0].name, "Shared-Services-PowerUser");
+        assert_eq!(plan.added[0].account_id, "555555555555");
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].name, "Sandbox-Admin");
+
+        plan.apply(&mut config);
+        assert!(!config.get_role("dev-ro").unwrap().stale);
+        assert!(config.get_role("old").unwrap().stale);
+        assert!(config.get_role("Shared-Services-PowerUser").is_some());
+
+        // Already stale roles are not reported again
+        let plan = plan_sync(&config, &discovered, DEFAULT_NAME_TEMPLATE).unwrap();
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn test_plan_sync_name_template() {
+        let discovered = vec![assignment("111111111111", "Production", "Admin")];
+        let plan = plan_sync(&Config::new(), &discovered, "{account_id}_{role_name}").unwrap();
+        assert_eq!(plan.added[0].name, "111111111111_Admin");
+
+        assert!(plan_sync(&Config::new(), &discovered, "{account}").is_err());
+    }
+}
@@ -1,6 +1,11 @@
+pub mod template;
+
+use crate::aws::arn::{Arn, Partition};
+use crate::aws::ClientSettings;
 use crate::error::{AppError, AppResult};
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -9,16 +14,159 @@ pub struct Config {
     pub default_profile: Option<String>,
     pub sso_start_url: Option<String>,
     pub sso_region: Option<String>,
+    /// Global RoleSessionName template, see [`template::TemplateContext`]
+    pub session_name: Option<String>,
+    /// Global SourceIdentity template, e.g. `{principal}` (default: none)
+    pub source_identity: Option<String>,
+    /// Name template for roles created by `awsr sso sync`
+    /// (default: `{account_name}-{role_name}`)
+    pub sso_name_template: Option<String>,
+    /// AWS region for roles without their own `region`
+    pub default_region: Option<String>,
+    /// Session duration in seconds for roles without their own `session_duration`
+    pub default_duration: Option<i64>,
+    /// STS and SSO endpoint for roles without their own `endpoint_url`, e.g. a
+    /// local emulator
+    pub endpoint_url: Option<String>,
+    /// Cached credentials are only reused while they have more than this many
+    /// seconds left (default: 300)
+    pub cache_expiry_margin_seconds: Option<u64>,
     pub roles: Vec<RoleConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How credentials for a role are obtained.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleKind {
+    /// `sts:AssumeRole` on `role_arn`
+    #[default]
+    Sts,
+    /// IAM Identity Center permission set `sso_role_name` in `account_id`,
+    /// fetched with `sso:GetRoleCredentials`
+    Sso,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RoleConfig {
     pub name: String,
+    /// Roles saved before SSO support have no kind and are STS roles
+    #[serde(default)]
+    pub kind: RoleKind,
+    /// Empty for SSO roles
+    #[serde(default)]
     pub role_arn: String,
     pub account_id: String,
+    /// Permission set name of an SSO role
+    #[serde(default)]
+    pub sso_role_name: Option<String>,
     pub source_profile: Option<String>,
     pub session_duration: Option<i64>,
+    /// ARN or serial number of the MFA device required by the role's trust policy
+    pub mfa_serial: Option<String>,
+    /// ExternalId required by third-party cross-account trust policies
+    pub external_id: Option<String>,
+    /// RoleSessionName template overriding the global one
+    pub session_name: Option<String>,
+    /// SourceIdentity template overriding the global one (`""` disables it)
+    pub source_identity: Option<String>,
+    /// AWS region for sessions of this role
+    pub region: Option<String>,
+    /// Call STS through its FIPS endpoint
+    pub use_fips_endpoint: Option<bool>,
+    /// Call STS through its dual-stack (IPv4 and IPv6) endpoint
+    #[serde(alias = "use_dualstack")]
+    pub use_dualstack_endpoint: Option<bool>,
+    /// STS and SSO endpoint overriding the global `endpoint_url`
+    pub endpoint_url: Option<String>,
+    /// Session tags sent with AssumeRole; values are templates like `session_name`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Keys of `tags` that stay on the session through later role chain hops
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitive_tag_keys: Vec<String>,
+    /// Inline session policy that narrows the role's permissions: JSON, or
+    /// `@path` to a JSON file
+    pub session_policy: Option<String>,
+    /// Managed policies that narrow the role's permissions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policy_arns: Vec<String>,
+    /// Configured role whose credentials are used to assume this one (role chaining)
+    #[serde(alias = "via")]
+    pub parent_role: Option<String>,
+    /// Set by `awsr sso sync` when the SSO assignment no longer exists
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+}
+
+/// Values from `assume` flags that override the role configuration for one
+/// session.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SessionOverrides<'a> {
+    /// Session name template replacing the configured one
+    pub session_name: Option<&'a str>,
+    /// Extra session tags, see [`RoleConfig::session_tags`]
+    pub tags: &'a [(String, String)],
+    /// Inline session policy (JSON or `@path`) replacing the role's `session_policy`
+    pub policy: Option<&'a str>,
+    /// Managed session policies replacing the role's `policy_arns` when not empty
+    pub policy_arns: &'a [String],
+}
+
+impl SessionOverrides<'_> {
+    /// The overrides for the intermediate hops of a role chain: tags and
+    /// session policies only scope the target role.
+    pub fn for_parent_roles(self) -> Self {
+        Self {
+            session_name: self.session_name,
+            ..Default::default()
+        }
+    }
+}
+
+impl RoleConfig {
+    /// The inline session policy source (JSON or `@path`) for a session.
+    pub fn session_policy_for<'a>(&'a self, overrides: &SessionOverrides<'a>) -> Option<&'a str> {
+        overrides.policy.or(self.session_policy.as_deref())
+    }
+
+    /// The managed session policy ARNs for a session.
+    pub fn policy_arns_for<'a>(&'a self, overrides: &SessionOverrides<'a>) -> &'a [String] {
+        if overrides.policy_arns.is_empty() {
+            &self.policy_arns
+        } else {
+            overrides.policy_arns
+        }
+    }
+
+    /// Session tag templates for this role: its `tags` plus `extra` (e.g. from
+    /// `--tag`), which replace configured tags with the same key ignoring case.
+    pub fn session_tags(&self, extra: &[(String, String)]) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = self
+            .tags
+            .iter()
+            .filter(|(key, _)| {
+                !extra
+                    .iter()
+                    .any(|(other, _)| other.eq_ignore_ascii_case(key))
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        tags.extend(extra.iter().cloned());
+        tags
+    }
+
+    /// What the role points at, for display: the role ARN, or the SSO account
+    /// and permission set.
+    pub fn target(&self) -> String {
+        match self.kind {
+            RoleKind::Sts => self.role_arn.clone(),
+            RoleKind::Sso => format!(
+                "sso: {}/{}",
+                self.account_id,
+                self.sso_role_name.as_deref().unwrap_or("?")
+            ),
+        }
+    }
 }
 
 impl Default for Config {
@@ -33,6 +181,13 @@ impl Config {
             default_profile: None,
             sso_start_url: None,
             sso_region: None,
+            session_name: None,
+            source_identity: None,
+            sso_name_template: None,
+            default_region: None,
+            default_duration: None,
+            endpoint_url: None,
+            cache_expiry_margin_seconds: None,
             roles: Vec::new(),
         }
     }
@@ -40,12 +195,7 @@ impl Config {
     pub fn load() -> AppResult<Self> {
         let config_path = Self::get_config_path()?;
         if !config_path.exists() {
-            return Ok(Config {
-                default_profile: None,
-                sso_start_url: None,
-                sso_region: None,
-                roles: Vec::new(),
-            });
+            return Ok(Config::new());
         }
 
         let content = fs::read_to_string(&config_path)
@@ -75,26 +225,7 @@ impl Config {
     }
 
     fn get_config_path() -> AppResult<PathBuf> {
-        // Check environment variables first for cross-platform compatibility
-        // This handles cases where HOME (Unix) or USERPROFILE (Windows) are set
-        if let Ok(home_path) = std::env::var("HOME") {
-            return Ok(PathBuf::from(home_path)
-                .join(".aws-assume-role")
-                .join("config.json"));
-        }
-
-        #[cfg(windows)]
-        if let Ok(userprofile_path) = std::env::var("USERPROFILE") {
-            return Ok(PathBuf::from(userprofile_path)
-                .join(".aws-assume-role")
-                .join("config.json"));
-        }
-
-        // Fallback to dirs::home_dir() for standard behavior
-        let home_dir = dirs::home_dir()
-            .ok_or_else(|| AppError::ConfigError("Could not find home directory".to_string()))?;
-
-        Ok(home_dir.join(".aws-assume-role").join("config.json"))
+        Ok(config_dir()?.join("config.json"))
     }
 
     pub fn add_role(&mut self, role: RoleConfig) {
@@ -117,6 +248,194 @@ impl Config {
             false
         }
     }
+
+    /// Resolve the AWS profile whose credentials are used to assume `role`.
+    ///
+    /// The role's own `source_profile` wins; otherwise `default_profile` is used.
+    /// `None` means the default AWS credential chain.
+    pub fn source_profile_for<'a>(&'a self, role: &'a RoleConfig) -> Option<&'a str> {
+        role.source_profile
+            .as_deref()
+            .or(self.default_profile.as_deref())
+    }
+
+    /// The roles to assume, in order, to reach `role`: its `parent_role`
+    /// ancestors root-first, ending with `role` itself. The root is assumed with
+    /// its source profile; every later hop uses the previous hop's credentials.
+    pub fn role_chain<'a>(&'a self, role: &'a RoleConfig) -> AppResult<Vec<&'a RoleConfig>> {
+        let mut chain = vec![role];
+        let mut current = role;
+
+        while let Some(parent_name) = &current.parent_role {
+            if current.kind == RoleKind::Sso {
+                return Err(AppError::ConfigError(format!(
+                    "SSO role '{}' cannot have a parent_role",
+                    current.name
+                )));
+            }
+            if chain.iter().any(|r| &r.name == parent_name) {
+                let mut names: Vec<&str> = chain.iter().map(|r| r.name.as_str()).collect();
+                names.push(parent_name);
+                return Err(AppError::ConfigError(format!(
+                    "Role chain cycle detected: {}",
+                    names.join(" -> ")
+                )));
+            }
+
+            let parent = self.get_role(parent_name).ok_or_else(|| {
+                AppError::ConfigError(format!(
+                    "Role '{}' has unknown parent_role '{}'",
+                    current.name, parent_name
+                ))
+            })?;
+            chain.push(parent);
+            current = parent;
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Resolve the region for sessions of `role`: its own `region`, then
+    /// `default_region`. `None` leaves the choice to the environment.
+    pub fn region_for<'a>(&'a self, role: &'a RoleConfig) -> Option<&'a str> {
+        role.region.as_deref().or(self.default_region.as_deref())
+    }
+
+    /// The partition of `role`: from its ARN, or for SSO roles from its region
+    /// or `sso_region`. `None` when the role ARN cannot be parsed.
+    pub fn partition_for(&self, role: &RoleConfig) -> Option<Partition> {
+        match role.kind {
+            RoleKind::Sts => Arn::parse(&role.role_arn).ok().map(|arn| arn.partition),
+            RoleKind::Sso => Some(
+                self.region_for(role)
+                    .or(self.sso_region.as_deref())
+                    .map(Partition::of_region)
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// Client settings for calls made on behalf of `role`, rejecting a region
+    /// outside the role's partition.
+    ///
+    /// A role ARN that cannot be parsed is left for STS to reject; its partition
+    /// is then taken from the region.
+    pub fn client_settings_for(&self, role: &RoleConfig) -> AppResult<ClientSettings> {
+        let region = self.region_for(role);
+        let partition = match (self.partition_for(role), region) {
+            (Some(partition), Some(region)) => {
+                partition
+                    .validate_region(region)
+                    .map_err(|e| e.with_context(&format!("Role '{}'", role.name)))?;
+                partition
+            }
+            (Some(partition), None) => partition,
+            (None, region) => region.map(Partition::of_region).unwrap_or_default(),
+        };
+        Ok(ClientSettings {
+            region: region.map(str::to_string),
+            partition,
+            use_fips_endpoint: role.use_fips_endpoint,
+            use_dualstack_endpoint: role.use_dualstack_endpoint,
+            endpoint_url: self.endpoint_url_for(Some(role)),
+        })
+    }
+
+    /// Client settings for calls not tied to a role, in `default_region`.
+    pub fn default_client_settings(&self) -> ClientSettings {
+        ClientSettings {
+            region: self.default_region.clone(),
+            partition: self
+                .default_region
+                .as_deref()
+                .map(Partition::of_region)
+                .unwrap_or_default(),
+            endpoint_url: self.endpoint_url_for(None),
+            ..Default::default()
+        }
+    }
+
+    /// Resolve the STS and SSO endpoint: `AWS_ENDPOINT_URL_STS`, then the
+    /// role's `endpoint_url`, then the global one. The environment wins so CI
+    /// can point an unchanged config at a local stand-in.
+    pub fn endpoint_url_for(&self, role: Option<&RoleConfig>) -> Option<String> {
+        std::env::var("AWS_ENDPOINT_URL_STS")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| role.and_then(|role| role.endpoint_url.clone()))
+            .or_else(|| self.endpoint_url.clone())
+    }
+
+    /// Resolve the session duration for `role`: the CLI value, then the role's
+    /// `session_duration`, then `default_duration`.
+    pub fn session_duration_for(
+        &self,
+        role: &RoleConfig,
+        cli_duration: Option<i32>,
+    ) -> AppResult<Option<i32>> {
+        let duration = match cli_duration {
+            Some(duration) => Some(i64::from(duration)),
+            None => role.session_duration.or(self.default_duration),
+        };
+        duration
+            .map(|duration| {
+                validate_session_duration(duration)?;
+                Ok(duration as i32)
+            })
+            .transpose()
+    }
+
+    /// Resolve the SourceIdentity template for `role`: its own, then the global
+    /// one. None is sent unless configured, and an empty template turns it off.
+    pub fn source_identity_for<'a>(&'a self, role: &'a RoleConfig) -> Option<&'a str> {
+        role.source_identity
+            .as_deref()
+            .or(self.source_identity.as_deref())
+            .filter(|template| !template.is_empty())
+    }
+
+    /// Resolve the session name template for `role`, falling back to the global one.
+    pub fn session_name_for<'a>(&'a self, role: &'a RoleConfig) -> Option<&'a str> {
+        role.session_name
+            .as_deref()
+            .or(self.session_name.as_deref())
+    }
+}
+
+/// Check a session duration against the range STS accepts (15 minutes to 12 hours).
+pub fn validate_session_duration(seconds: i64) -> AppResult<()> {
+    if (900..=43200).contains(&seconds) {
+        Ok(())
+    } else {
+        Err(AppError::ConfigError(format!(
+            "Invalid session duration {}s: must be between 900 and 43200 seconds",
+            seconds
+        )))
+    }
+}
+
+/// Directory holding awsr's own files (`~/.aws-assume-role`).
+pub fn config_dir() -> AppResult<PathBuf> {
+    Ok(home_dir()?.join(".aws-assume-role"))
+}
+
+/// Resolve the user's home directory.
+pub fn home_dir() -> AppResult<PathBuf> {
+    // Check environment variables first for cross-platform compatibility
+    // This handles cases where HOME (Unix) or USERPROFILE (Windows) are set
+    if let Ok(home_path) = std::env::var("HOME") {
+        return Ok(PathBuf::from(home_path));
+    }
+
+    #[cfg(windows)]
+    if let Ok(userprofile_path) = std::env::var("USERPROFILE") {
+        return Ok(PathBuf::from(userprofile_path));
+    }
+
+    // Fallback to dirs::home_dir() for standard behavior
+    dirs::home_dir()
+        .ok_or_else(|| AppError::ConfigError("Could not find home directory".to_string()))
 }
 
 #[cfg(test)]
@@ -133,6 +452,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         assert_eq!(role.name, "test-role");
@@ -156,6 +476,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         config.add_role(role);
@@ -172,6 +493,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         config.add_role(role);
@@ -193,6 +515,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         config.add_role(role);
@@ -215,6 +538,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: Some("default".to_string()),
             session_duration: Some(7200),
+            ..Default::default()
         };
 
         config.add_role(role);
@@ -229,6 +553,26 @@ mod tests {
         assert_eq!(role.session_duration, Some(7200));
     }
 
+    #[test]
+    fn test_mfa_serial_round_trip() {
+        let legacy = r#"{"roles": [{"name": "dev", "role_arn": "arn:aws:iam::123456789012:role/Dev", "account_id": "123456789012", "source_profile": null, "session_duration": null}]}"#;
+        let config: Config = serde_json::from_str(legacy).unwrap();
+        assert_eq!(config.get_role("dev").unwrap().mfa_serial, None);
+
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "prod".to_string(),
+            mfa_serial: Some("arn:aws:iam::123456789012:mfa/user".to_string()),
+            ..Default::default()
+        });
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            deserialized.get_role("prod").unwrap().mfa_serial.as_deref(),
+            Some("arn:aws:iam::123456789012:mfa/user")
+        );
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_save_and_load_config() {
@@ -243,6 +587,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         config.add_role(role);
@@ -316,6 +661,319 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_source_profile_fallback() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "test-role".to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/TestRole".to_string(),
+            account_id: "123456789012".to_string(),
+            source_profile: None,
+            session_duration: None,
+            ..Default::default()
+        };
+
+        assert_eq!(config.source_profile_for(&role), None);
+
+        config.default_profile = Some("base".to_string());
+        assert_eq!(config.source_profile_for(&role), Some("base"));
+
+        role.source_profile = Some("dev-base".to_string());
+        assert_eq!(config.source_profile_for(&role), Some("dev-base"));
+    }
+
+    #[test]
+    fn test_session_name_fallback() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "test-role".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.session_name_for(&role), None);
+
+        config.session_name = Some("{user}".to_string());
+        assert_eq!(config.session_name_for(&role), Some("{user}"));
+
+        role.session_name = Some("{user}-{role}".to_string());
+        assert_eq!(config.session_name_for(&role), Some("{user}-{role}"));
+    }
+
+    #[test]
+    fn test_source_identity_fallback() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "test-role".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.source_identity_for(&role), None);
+
+        config.source_identity = Some("{user}".to_string());
+        assert_eq!(config.source_identity_for(&role), Some("{user}"));
+
+        role.source_identity = Some(String::new());
+        assert_eq!(config.source_identity_for(&role), None);
+    }
+
+    #[test]
+    fn test_region_fallback() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "test-role".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.region_for(&role), None);
+
+        config.default_region = Some("eu-west-1".to_string());
+        assert_eq!(config.region_for(&role), Some("eu-west-1"));
+
+        role.region = Some("ap-southeast-2".to_string());
+        assert_eq!(config.region_for(&role), Some("ap-southeast-2"));
+    }
+
+    #[test]
+    fn test_client_settings_partition() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "gov".to_string(),
+            role_arn: "arn:aws-us-gov:iam::123456789012:role/Admin".to_string(),
+            use_fips_endpoint: Some(true),
+            ..Default::default()
+        };
+
+        let settings = config.client_settings_for(&role).unwrap();
+        assert_eq!(settings.partition, Partition::AwsUsGov);
+        assert_eq!(settings.region, None);
+        assert_eq!(settings.use_fips_endpoint, Some(true));
+
+        config.default_region = Some("us-east-1".to_string());
+        let err = config.client_settings_for(&role).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Role 'gov': Region 'us-east-1' belongs to partition 'aws', not 'aws-us-gov'"
+        );
+
+        role.region = Some("us-gov-east-1".to_string());
+        let settings = config.client_settings_for(&role).unwrap();
+        assert_eq!(settings.region.as_deref(), Some("us-gov-east-1"));
+
+        role.role_arn = "not-an-arn".to_string();
+        let settings = config.client_settings_for(&role).unwrap();
+        assert_eq!(settings.partition, Partition::AwsUsGov);
+    }
+
+    #[test]
+    fn test_endpoint_url_fallback() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "local".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.endpoint_url_for(Some(&role)), None);
+
+        config.endpoint_url = Some("http://localhost:4566".to_string());
+        assert_eq!(
+            config.default_client_settings().endpoint_url.as_deref(),
+            Some("http://localhost:4566")
+        );
+
+        role.endpoint_url = Some("http://localhost:5000".to_string());
+        assert_eq!(
+            config
+                .client_settings_for(&role)
+                .unwrap()
+                .endpoint_url
+                .as_deref(),
+            Some("http://localhost:5000")
+        );
+    }
+
+    #[test]
+    fn test_session_tags_override() {
+        let json = r#"{"name":"dev","role_arn":"arn:aws:iam::123456789012:role/Dev","account_id":"123456789012","source_profile":null,"session_duration":null,"tags":{"team":"platform","owner":"{user}"},"transitive_tag_keys":["team"]}"#;
+        let role: RoleConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(role.transitive_tag_keys, vec!["team"]);
+
+        let extra = vec![("Team".to_string(), "security".to_string())];
+        assert_eq!(
+            role.session_tags(&extra),
+            vec![
+                ("owner".to_string(), "{user}".to_string()),
+                ("Team".to_string(), "security".to_string()),
+            ]
+        );
+
+        let plain = RoleConfig::default();
+        assert!(!serde_json::to_string(&plain).unwrap().contains("tags"));
+    }
+
+    #[test]
+    fn test_session_policy_overrides() {
+        let role = RoleConfig {
+            name: "dev".to_string(),
+            session_policy: Some("@readonly.json".to_string()),
+            policy_arns: vec!["arn:aws:iam::aws:policy/ReadOnlyAccess".to_string()],
+            ..Default::default()
+        };
+
+        let none = SessionOverrides::default();
+        assert_eq!(role.session_policy_for(&none), Some("@readonly.json"));
+        assert_eq!(role.policy_arns_for(&none), role.policy_arns.as_slice());
+
+        let arns = vec!["arn:aws:iam::aws:policy/AmazonS3ReadOnlyAccess".to_string()];
+        let overrides = SessionOverrides {
+            policy: Some("{}"),
+            policy_arns: &arns,
+            ..Default::default()
+        };
+        assert_eq!(role.session_policy_for(&overrides), Some("{}"));
+        assert_eq!(role.policy_arns_for(&overrides), arns.as_slice());
+
+        let parents = overrides.for_parent_roles();
+        assert_eq!(parents.policy, None);
+        assert!(parents.policy_arns.is_empty());
+    }
+
+    #[test]
+    fn test_sso_role_partition_from_region() {
+        let mut config = Config::new();
+        config.sso_region = Some("cn-north-1".to_string());
+        let role = RoleConfig {
+            name: "cn".to_string(),
+            kind: RoleKind::Sso,
+            ..Default::default()
+        };
+        assert_eq!(config.partition_for(&role), Some(Partition::AwsCn));
+    }
+
+    #[test]
+    fn test_session_duration_precedence() {
+        let mut config = Config::new();
+        let mut role = RoleConfig {
+            name: "test-role".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.session_duration_for(&role, None).unwrap(), None);
+
+        config.default_duration = Some(1800);
+        assert_eq!(
+            config.session_duration_for(&role, None).unwrap(),
+            Some(1800)
+        );
+
+        role.session_duration = Some(7200);
+        assert_eq!(
+            config.session_duration_for(&role, None).unwrap(),
+            Some(7200)
+        );
+        assert_eq!(
+            config.session_duration_for(&role, Some(900)).unwrap(),
+            Some(900)
+        );
+
+        role.session_duration = Some(60);
+        assert!(config.session_duration_for(&role, None).is_err());
+    }
+
+    #[test]
+    fn test_role_chain() {
+        let mut config = Config::new();
+        let role = |name: &str, parent: Option<&str>| RoleConfig {
+            name: name.to_string(),
+            parent_role: parent.map(str::to_string),
+            ..Default::default()
+        };
+        config.add_role(role("hub", None));
+        config.add_role(role("prod", Some("hub")));
+
+        let names = |chain: Vec<&RoleConfig>| -> Vec<String> {
+            chain.iter().map(|r| r.name.clone()).collect()
+        };
+        let hub = config.get_role("hub").unwrap();
+        assert_eq!(names(config.role_chain(hub).unwrap()), vec!["hub"]);
+        let prod = config.get_role("prod").unwrap();
+        assert_eq!(names(config.role_chain(prod).unwrap()), vec!["hub", "prod"]);
+
+        let orphan = role("orphan", Some("missing"));
+        let err = config.role_chain(&orphan).unwrap_err().to_string();
+        assert!(err.contains("unknown parent_role 'missing'"));
+
+        config.add_role(role("hub", Some("prod")));
+        let hub = config.get_role("hub").unwrap();
+        let err = config.role_chain(hub).unwrap_err().to_string();
+        assert_eq!(err, "Role chain cycle detected: hub -> prod -> hub");
+
+        let looped = role("self", Some("self"));
+        assert!(config.role_chain(&looped).is_err());
+    }
+
+    #[test]
+    fn test_role_kind_defaults_to_sts() {
+        let json = r#"{"name":"dev","role_arn":"arn:aws:iam::123456789012:role/Dev","account_id":"123456789012","source_profile":null,"session_duration":null}"#;
+        let role: RoleConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(role.kind, RoleKind::Sts);
+        assert_eq!(role.target(), "arn:aws:iam::123456789012:role/Dev");
+
+        let json = r#"{"name":"admin","kind":"sso","account_id":"123456789012","sso_role_name":"AdministratorAccess","source_profile":null,"session_duration":null}"#;
+        let role: RoleConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(role.kind, RoleKind::Sso);
+        assert_eq!(role.role_arn, "");
+        assert_eq!(role.target(), "sso: 123456789012/AdministratorAccess");
+
+        let serialized = serde_json::to_string(&role).unwrap();
+        assert!(serialized.contains(r#""kind":"sso""#));
+    }
+
+    #[test]
+    fn test_sso_role_cannot_be_chained() {
+        let mut config = Config::new();
+        config.add_role(RoleConfig {
+            name: "hub".to_string(),
+            ..Default::default()
+        });
+        config.add_role(RoleConfig {
+            name: "admin".to_string(),
+            kind: RoleKind::Sso,
+            parent_role: Some("hub".to_string()),
+            ..Default::default()
+        });
+
+        let admin = config.get_role("admin").unwrap();
+        let err = config.role_chain(admin).unwrap_err().to_string();
+        assert_eq!(err, "SSO role 'admin' cannot have a parent_role");
+
+        // An SSO role can be the root of a chain
+        config.add_role(RoleConfig {
+            name: "admin".to_string(),
+            kind: RoleKind::Sso,
+            ..Default::default()
+        });
+        let prod = RoleConfig {
+            name: "prod".to_string(),
+            parent_role: Some("admin".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.role_chain(&prod).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_parent_role_via_alias() {
+        let json = r#"{"name":"prod","role_arn":"arn:aws:iam::123456789012:role/Prod","account_id":"123456789012","source_profile":null,"session_duration":null,"via":"hub"}"#;
+        let role: RoleConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(role.parent_role.as_deref(), Some("hub"));
+    }
+
+    #[test]
+    fn test_validate_session_duration() {
+        assert!(validate_session_duration(900).is_ok());
+        assert!(validate_session_duration(43200).is_ok());
+        assert!(validate_session_duration(899).is_err());
+        assert!(validate_session_duration(43201).is_err());
+    }
+
     #[test]
     fn test_duplicate_role_names() {
         let mut config = Config::new();
@@ -326,6 +984,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         };
 
         let role2 = RoleConfig {
@@ -334,6 +993,7 @@ mod tests {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(7200),
+            ..Default::default()
         };
 
         config.add_role(role1);
@@ -0,0 +1,171 @@
+//! `{placeholder}` templates used in role configuration values such as the
+//! session name.
+
+use crate::error::{AppError, AppResult};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Values available to templates.
+#[derive(Debug, Clone)]
+pub struct TemplateContext {
+    /// Name of the role configuration being assumed (`{role}`)
+    pub role: String,
+    /// Local user name (`{user}`)
+    pub user: String,
+    /// Local host name (`{hostname}`)
+    pub hostname: String,
+    /// Unix time in seconds (`{timestamp}`)
+    pub timestamp: u64,
+    /// Caller identity user id of the base credentials (`{user_id}`)
+    pub user_id: Option<String>,
+    /// The human behind the session (`{principal}`): the IAM Identity Center
+    /// user when the base credentials come from SSO, otherwise `user`
+    pub principal: Option<String>,
+}
+
+impl TemplateContext {
+    pub fn new(role: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            user: local_user(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            user_id: None,
+            principal: None,
+        }
+    }
+
+    fn value(&self, placeholder: &str) -> AppResult<String> {
+        match placeholder {
+            "role" => Ok(self.role.clone()),
+            "user" => Ok(self.user.clone()),
+            "hostname" => Ok(self.hostname.clone()),
+            "timestamp" => Ok(self.timestamp.to_string()),
+            "user_id" => self.user_id.clone().ok_or_else(|| {
+                AppError::ConfigError("Caller identity is not available for {user_id}".to_string())
+            }),
+            "principal" => Ok(self.principal.clone().unwrap_or_else(|| self.user.clone())),
+            other => Err(unknown_placeholder(other)),
+        }
+    }
+
+    /// Substitute placeholders in `template`, passing every substituted value
+    /// through `escape` so callers can enforce their own character set.
+    pub fn render(&self, template: &str, escape: impl Fn(&str) -> String) -> AppResult<String> {
+        render_with(template, |placeholder| self.value(placeholder), escape)
+    }
+}
+
+/// Substitute placeholders in `template` with values from `value`, which
+/// rejects placeholders it does not know.
+pub fn render_with(
+    template: &str,
+    value: impl Fn(&str) -> AppResult<String>,
+    escape: impl Fn(&str) -> String,
+) -> AppResult<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after.find('}').ok_or_else(|| {
+            AppError::ConfigError(format!("Unclosed placeholder in template '{}'", template))
+        })?;
+        output.push_str(&escape(&value(&after[..end])?));
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// The error for a placeholder a template does not support.
+pub fn unknown_placeholder(placeholder: &str) -> AppError {
+    AppError::ConfigError(format!(
+        "Unknown template placeholder '{{{}}}'",
+        placeholder
+    ))
+}
+
+/// Whether `template` references `placeholder` (given without braces).
+pub fn uses_placeholder(template: &str, placeholder: &str) -> bool {
+    template.contains(&format!("{{{}}}", placeholder))
+}
+
+/// Local user name from the environment.
+pub fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            role: "dev".to_string(),
+            user: "alice".to_string(),
+            hostname: "laptop".to_string(),
+            timestamp: 1700000000,
+            user_id: None,
+            principal: None,
+        }
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let rendered = context()
+            .render("{user}@{hostname}-{role}-{timestamp}", str::to_string)
+            .unwrap();
+        assert_eq!(rendered, "alice@laptop-dev-1700000000");
+    }
+
+    #[test]
+    fn test_render_escapes_values_only() {
+        let mut ctx = context();
+        ctx.user = "Alice Smith".to_string();
+        let rendered = ctx.render("x y-{user}", |v| v.replace(' ', "_")).unwrap();
+        assert_eq!(rendered, "x y-Alice_Smith");
+    }
+
+    #[test]
+    fn test_render_principal() {
+        let mut ctx = context();
+        assert_eq!(ctx.render("{principal}", str::to_string).unwrap(), "alice");
+
+        ctx.principal = Some("alice@example.com".to_string());
+        assert_eq!(
+            ctx.render("{principal}", str::to_string).unwrap(),
+            "alice@example.com"
+        );
+    }
+
+    #[test]
+    fn test_render_user_id() {
+        let mut ctx = context();
+        assert!(ctx.render("{user_id}", str::to_string).is_err());
+
+        ctx.user_id = Some("AIDAEXAMPLE".to_string());
+        assert_eq!(
+            ctx.render("{user_id}", str::to_string).unwrap(),
+            "AIDAEXAMPLE"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert!(context().render("{nope}", str::to_string).is_err());
+        assert!(context().render("{user", str::to_string).is_err());
+    }
+
+    #[test]
+    fn test_uses_placeholder() {
+        assert!(uses_placeholder("awsr-{user_id}", "user_id"));
+        assert!(!uses_placeholder("awsr-{user}", "user_id"));
+    }
+}
@@ -21,6 +21,20 @@ impl fmt::Display for AppError {
     }
 }
 
+impl AppError {
+    /// Prefix the message with `context`, keeping the error kind.
+    pub fn with_context(self, context: &str) -> Self {
+        match self {
+            AppError::AwsError(msg) => AppError::AwsError(format!("{}: {}", context, msg)),
+            AppError::ConfigError(msg) => AppError::ConfigError(format!("{}: {}", context, msg)),
+            AppError::CliError(msg) => AppError::CliError(format!("{}: {}", context, msg)),
+            AppError::IoError(e) => {
+                AppError::IoError(std::io::Error::new(e.kind(), format!("{}: {}", context, e)))
+            }
+        }
+    }
+}
+
 impl Error for AppError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
@@ -117,6 +131,18 @@ mod tests {
         assert_eq!(display, "Config error message");
     }
 
+    #[test]
+    fn test_error_with_context() {
+        let error = AppError::AwsError("Access denied".to_string()).with_context("Hop 2");
+        assert!(matches!(error, AppError::AwsError(_)));
+        assert_eq!(error.to_string(), "Hop 2: Access denied");
+
+        let error = AppError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, "missing"))
+            .with_context("Hop 1");
+        assert!(matches!(&error, AppError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(error.to_string(), "Hop 1: missing");
+    }
+
     #[test]
     fn test_error_debug() {
         let error = AppError::CliError("Debug test".to_string());
@@ -4,6 +4,7 @@
 //! across different accounts with SSO federated access.
 
 pub mod aws;
+pub mod cache;
 pub mod cli;
 pub mod config;
 pub mod error;
@@ -1,4 +1,5 @@
 mod aws;
+mod cache;
 mod cli;
 mod config;
 mod error;
@@ -7,8 +8,11 @@ use error::AppResult;
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Initialize logging. Logs go to stderr so stdout only carries command
+    // output (credential exports, credential_process JSON).
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 
     // Run the CLI
     cli::Cli::run().await
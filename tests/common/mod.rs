@@ -1,6 +1,9 @@
+use assert_cmd::Command;
 use aws_assume_role::config::{Config, RoleConfig};
 use std::fs;
+use std::path::Path;
 use tempfile::TempDir;
+use wiremock::ResponseTemplate;
 
 /// Test utilities and helper functions for AWS Assume Role testing
 pub struct TestHelper {
@@ -33,6 +36,7 @@ impl TestHelper {
             account_id: "123456789012".to_string(),
             source_profile: None,
             session_duration: Some(3600),
+            ..Default::default()
         }
     }
 
@@ -49,6 +53,7 @@ impl TestHelper {
             account_id: account_id.to_string(),
             source_profile: None,
             session_duration: duration,
+            ..Default::default()
         }
     }
 
@@ -170,6 +175,90 @@ impl MockCredentials {
     }
 }
 
+/// Write `config` as `config.json` under `home`'s awsr directory
+#[allow(dead_code)]
+pub fn write_config(home: &Path, config: &str) {
+    let config_dir = home.join(".aws-assume-role");
+    fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+    fs::write(config_dir.join("config.json"), config).expect("Failed to write config");
+}
+
+/// Cache unexpiring credentials (`ASIACACHEDEXAMPLE`) for a role with default settings
+#[allow(dead_code)]
+pub fn write_cached_entry(home: &Path, role_name: &str, role_arn: &str) {
+    let cache_dir = home.join(".aws-assume-role").join("cache");
+    fs::create_dir_all(&cache_dir).expect("Failed to create cache directory");
+    fs::write(
+        cache_dir.join(format!("{}.json", role_name)),
+        format!(
+            r#"{{
+                "key": {{
+                    "role_name": "{}",
+                    "role_arn": "{}",
+                    "source_profile": null,
+                    "duration_seconds": null,
+                    "external_id": null,
+                    "mfa_serial": null,
+                    "session_name": null
+                }},
+                "access_key_id": "ASIACACHEDEXAMPLE",
+                "secret_access_key": "cached-secret",
+                "session_token": "cached-token",
+                "issued_at": 1700000000,
+                "expiration": 4102444800
+            }}"#,
+            role_name, role_arn
+        ),
+    )
+    .expect("Failed to write cache entry");
+}
+
+/// An awsr command using `home` and static test credentials, so it never
+/// touches the real configuration or an endpoint set in the environment
+#[allow(dead_code)]
+pub fn awsr_cmd(home: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("aws-assume-role").expect("Failed to find awsr binary");
+    cmd.env("HOME", home);
+    cmd.env("AWS_ACCESS_KEY_ID", "test");
+    cmd.env("AWS_SECRET_ACCESS_KEY", "test");
+    cmd.env_remove("AWS_ENDPOINT_URL_STS");
+
+    // On Windows, also set USERPROFILE for proper home directory detection
+    #[cfg(windows)]
+    cmd.env("USERPROFILE", home);
+
+    cmd
+}
+
+/// A successful STS AssumeRole response issuing `access_key_id`
+#[allow(dead_code)]
+pub fn assume_role_response(
+    access_key_id: &str,
+    source_identity: Option<&str>,
+) -> ResponseTemplate {
+    let source_identity = source_identity
+        .map(|identity| format!("<SourceIdentity>{}</SourceIdentity>", identity))
+        .unwrap_or_default();
+    ResponseTemplate::new(200).set_body_string(format!(
+        "<AssumeRoleResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+         <AssumeRoleResult>\
+         <Credentials>\
+         <AccessKeyId>{}</AccessKeyId>\
+         <SecretAccessKey>test-secret</SecretAccessKey>\
+         <SessionToken>test-token</SessionToken>\
+         <Expiration>2100-01-01T00:00:00Z</Expiration>\
+         </Credentials>\
+         <AssumedRoleUser>\
+         <Arn>arn:aws:sts::000000000000:assumed-role/Dev/awsr</Arn>\
+         <AssumedRoleId>AROATEST:awsr</AssumedRoleId>\
+         </AssumedRoleUser>\
+         {}\
+         </AssumeRoleResult>\
+         </AssumeRoleResponse>",
+        access_key_id, source_identity
+    ))
+}
+
 /// Test data constants
 #[allow(dead_code)]
 pub mod test_data {
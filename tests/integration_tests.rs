@@ -3,6 +3,8 @@ use predicates::prelude::*;
 use std::fs;
 use tempfile::TempDir;
 
+mod common;
+
 /// Integration tests for AWS Assume Role CLI
 ///
 /// These tests verify the end-to-end functionality of the CLI application
@@ -184,6 +186,50 @@ mod config_integration {
             .success()
             .stdout(predicate::str::contains("test-role"));
 
+        // Configure a role with an external ID and check it is masked in list output
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args([
+            "configure",
+            "--name",
+            "vendor-role",
+            "--role-arn",
+            "arn:aws:iam::123456789012:role/VendorRole",
+            "--account-id",
+            "123456789012",
+            "--external-id",
+            "secret-external-id-9876",
+        ])
+        .assert()
+        .success();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.arg("list")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("External ID: ****9876"))
+            .stdout(predicate::str::contains("secret-external-id").not());
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args(["remove", "vendor-role"]).assert().success();
+
         // Test remove command
         let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
         cmd.env("HOME", temp_dir.path());
@@ -239,6 +285,25 @@ mod error_handling {
         .stdout(predicate::str::contains("could not verify"));
     }
 
+    /// Test that credential_process errors go to stderr only
+    #[test]
+    fn test_credential_process_error_output() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args(["assume", "missing", "--format", "credential-process"])
+            .assert()
+            .failure()
+            .stdout(predicate::str::is_empty())
+            .stderr(predicate::str::contains("Role 'missing' not found"));
+    }
+
     /// Test error handling for invalid account ID (graceful degradation)
     #[test]
     fn test_invalid_account_id() {
@@ -264,4 +329,919 @@ mod error_handling {
         .success()
         .stdout(predicate::str::contains("could not verify"));
     }
+
+    /// Test that a missing source profile is reported instead of silently ignored
+    #[test]
+    fn test_assume_missing_source_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "source_profile": "missing-profile",
+                        "session_duration": null
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+        cmd.env("AWS_CONFIG_FILE", temp_dir.path().join("aws-config"));
+        cmd.env(
+            "AWS_SHARED_CREDENTIALS_FILE",
+            temp_dir.path().join("aws-credentials"),
+        );
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args(["assume", "dev"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "AWS profile 'missing-profile' not found",
+            ));
+    }
+
+    /// Test that an invalid session name is rejected before calling STS
+    #[test]
+    fn test_assume_invalid_session_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "source_profile": null,
+                        "session_duration": null
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args(["assume", "dev", "--session-name", "not valid {role}"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid session name"));
+    }
+
+    /// Test that valid cached credentials are reused without calling STS
+    #[test]
+    fn test_assume_uses_cached_credentials() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(config_dir.join("cache")).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "source_profile": null,
+                        "session_duration": null
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("cache").join("dev.json"),
+            r#"{
+                "key": {
+                    "role_name": "dev",
+                    "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                    "source_profile": null,
+                    "duration_seconds": null,
+                    "external_id": null,
+                    "mfa_serial": null,
+                    "session_name": null
+                },
+                "access_key_id": "ASIACACHEDEXAMPLE",
+                "secret_access_key": "cached-secret",
+                "session_token": "cached-token",
+                "issued_at": 1700000000,
+                "expiration": 4102444800
+            }"#,
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args(["assume", "dev", "--format", "json"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("ASIACACHEDEXAMPLE"));
+
+        // credential_process output must be exactly one JSON document on stdout
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        let output = cmd
+            .args(["assume", "dev", "--format", "credential-process"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(json["Version"], 1);
+        assert_eq!(json["AccessKeyId"], "ASIACACHEDEXAMPLE");
+        assert_eq!(json["SessionToken"], "cached-token");
+        assert_eq!(json["Expiration"], "2100-01-01T00:00:00Z");
+
+        // credentials-file output updates one profile and keeps the rest
+        let credentials_file = temp_dir.path().join("aws-credentials");
+        fs::write(
+            &credentials_file,
+            "# personal keys\n[default]\naws_access_key_id = AKIAUSER\n\n[dev-session]\naws_access_key_id = OLD\n",
+        )
+        .unwrap();
+
+        let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+        cmd.env("HOME", temp_dir.path());
+        cmd.env("AWS_SHARED_CREDENTIALS_FILE", &credentials_file);
+
+        // On Windows, also set USERPROFILE for proper home directory detection
+        #[cfg(windows)]
+        cmd.env("USERPROFILE", temp_dir.path());
+
+        cmd.args([
+            "assume",
+            "dev",
+            "--format",
+            "credentials-file",
+            "--profile-name",
+            "dev-session",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+        let content = fs::read_to_string(&credentials_file).unwrap();
+        assert!(content.starts_with(
+            "# personal keys\n[default]\naws_access_key_id = AKIAUSER\n\n[dev-session]\n"
+        ));
+        assert!(content.contains("expires 2100-01-01T00:00:00Z"));
+        assert!(content.contains("aws_access_key_id = ASIACACHEDEXAMPLE\n"));
+        assert!(content.contains("aws_secret_access_key = cached-secret\n"));
+        assert!(content.contains("aws_session_token = cached-token\n"));
+        assert!(!content.contains("OLD"));
+    }
+
+    /// Test cache inspection and eviction without exposing secrets
+    #[test]
+    fn test_cache_list_and_clear() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_dir = temp_dir.path().join(".aws-assume-role").join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::write(
+            cache_dir.join("dev.json"),
+            r#"{
+                "key": {
+                    "role_name": "dev",
+                    "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                    "source_profile": null,
+                    "duration_seconds": null,
+                    "external_id": null,
+                    "mfa_serial": null,
+                    "session_name": null
+                },
+                "access_key_id": "ASIACACHEDEXAMPLE",
+                "secret_access_key": "cached-secret",
+                "session_token": "cached-token",
+                "assumed_role_arn": "arn:aws:sts::123456789012:assumed-role/DevRole/awsr",
+                "issued_at": 1700000000,
+                "expiration": 4102444800
+            }"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert().success()
+        };
+
+        awsr(&["cache", "list"])
+            .stdout(predicate::str::contains(
+                "arn:aws:sts::123456789012:assumed-role/DevRole/awsr",
+            ))
+            .stdout(predicate::str::contains("2023-11-14T22:13:20Z"))
+            .stdout(predicate::str::contains("cached-secret").not())
+            .stdout(predicate::str::contains("ASIACACHEDEXAMPLE").not());
+
+        awsr(&["cache", "list", "--format", "json"])
+            .stdout(predicate::str::contains("\"role\": \"dev\""))
+            .stdout(predicate::str::contains("cached-token").not());
+
+        awsr(&["cache", "clear", "dev"]).stdout(predicate::str::contains("removed"));
+        awsr(&["cache", "list"]).stdout(predicate::str::contains("No cached credentials"));
+    }
+
+    /// Test merging exported profiles into the AWS config file
+    #[test]
+    fn test_export_profiles_merge() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "source_profile": null,
+                        "session_duration": null
+                    },
+                    {
+                        "name": "prod",
+                        "role_arn": "arn:aws:iam::987654321098:role/ProdRole",
+                        "account_id": "987654321098",
+                        "source_profile": "base",
+                        "session_duration": 7200
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let aws_config = temp_dir.path().join("aws-config");
+        fs::write(
+            &aws_config,
+            "[default]\nregion = us-east-1\n\n[profile dev]\nregion = eu-west-1\n",
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+            cmd.env("AWS_CONFIG_FILE", &aws_config);
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(["export-profiles", "--merge"])
+                .assert()
+                .success()
+                .stderr(predicate::str::contains("Skipping 'dev'"));
+        }
+
+        let content = fs::read_to_string(&aws_config).unwrap();
+        assert!(content
+            .starts_with("[default]\nregion = us-east-1\n\n[profile dev]\nregion = eu-west-1\n"));
+        assert_eq!(content.matches("[profile prod]").count(), 1);
+        assert_eq!(content.matches("[profile dev]").count(), 1);
+        assert!(content.contains("role_arn = arn:aws:iam::987654321098:role/ProdRole\nsource_profile = base\nduration_seconds = 7200\n"));
+    }
+
+    /// Test importing role profiles from an AWS config file
+    #[test]
+    fn test_import_aws_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let aws_config = temp_dir.path().join("aws-config");
+        fs::write(
+            &aws_config,
+            "[profile dev]\nrole_arn = arn:aws:iam::123456789012:role/DevRole\n\n[profile plain]\nregion = us-east-1\n",
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert().success()
+        };
+        let path = aws_config.to_str().unwrap();
+
+        awsr(&["import", "aws-config", "--path", path, "--dry-run"])
+            .stdout(predicate::str::contains("Would import 'dev'"));
+        awsr(&["list"]).stdout(predicate::str::contains("No roles configured"));
+
+        awsr(&["import", "aws-config", "--path", path])
+            .stdout(predicate::str::contains("Imported 1 role(s)"));
+        awsr(&["list"])
+            .stdout(predicate::str::contains(
+                "dev (arn:aws:iam::123456789012:role/DevRole)",
+            ))
+            .stdout(predicate::str::contains("plain").not());
+
+        awsr(&["import", "aws-config", "--path", path])
+            .stdout(predicate::str::contains("already up to date"));
+    }
+
+    /// Test that durations outside the STS range are rejected before any AWS call
+    #[test]
+    fn test_assume_duration_out_of_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{"roles":[{"name":"dev","role_arn":"arn:aws:iam::123456789012:role/DevRole","account_id":"123456789012","session_duration":60}]}"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert().failure()
+        };
+
+        awsr(&["assume", "dev", "--duration", "60"])
+            .stderr(predicate::str::contains("900..=43200"));
+        awsr(&["assume", "dev", "--no-cache"])
+            .stderr(predicate::str::contains("Invalid session duration 60s"));
+    }
+
+    /// Test that role chain configuration errors are reported before any AWS call
+    #[test]
+    fn test_role_chain_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{"roles":[
+                {"name":"hub","role_arn":"arn:aws:iam::111111111111:role/Hub","account_id":"111111111111","parent_role":"prod"},
+                {"name":"prod","role_arn":"arn:aws:iam::222222222222:role/Prod","account_id":"222222222222","parent_role":"hub"},
+                {"name":"orphan","role_arn":"arn:aws:iam::333333333333:role/Orphan","account_id":"333333333333","via":"missing"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert()
+        };
+
+        awsr(&["assume", "prod", "--no-cache"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Role chain cycle detected: prod -> hub -> prod",
+            ));
+        awsr(&["assume", "orphan", "--no-cache"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Role 'orphan' has unknown parent_role 'missing'",
+            ));
+        awsr(&["list"])
+            .success()
+            .stdout(predicate::str::contains("Via: missing"));
+    }
+
+    /// Test that SSO roles are listed and need an SSO configuration to assume
+    #[test]
+    fn test_sso_role() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{"roles":[
+                {"name":"dev","role_arn":"arn:aws:iam::123456789012:role/DevRole","account_id":"123456789012"},
+                {"name":"admin","kind":"sso","account_id":"210987654321","sso_role_name":"AdministratorAccess"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert()
+        };
+
+        awsr(&["list"])
+            .success()
+            .stdout(predicate::str::contains(
+                "dev (arn:aws:iam::123456789012:role/DevRole)",
+            ))
+            .stdout(predicate::str::contains(
+                "admin (sso: 210987654321/AdministratorAccess)",
+            ));
+        awsr(&["assume", "admin", "--no-cache"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "SSO role 'admin' needs sso_start_url and sso_region",
+            ));
+    }
+
+    /// Test that the role's region is exported with the credentials
+    #[test]
+    fn test_assume_exports_role_region() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(config_dir.join("cache")).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{
+                "default_region": "us-west-2",
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "region": "eu-west-1"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("cache").join("dev.json"),
+            r#"{
+                "key": {
+                    "role_name": "dev",
+                    "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                    "source_profile": null,
+                    "duration_seconds": null,
+                    "external_id": null,
+                    "mfa_serial": null,
+                    "session_name": null
+                },
+                "access_key_id": "ASIACACHEDEXAMPLE",
+                "secret_access_key": "cached-secret",
+                "session_token": "cached-token",
+                "issued_at": 1700000000,
+                "expiration": 4102444800
+            }"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+            cmd.env("SHELL", "/bin/bash");
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert().success()
+        };
+
+        awsr(&["assume", "dev", "--format", "json"])
+            .stdout(predicate::str::contains("\"Region\": \"eu-west-1\""));
+
+        #[cfg(unix)]
+        {
+            awsr(&["assume", "dev", "--format", "export"])
+                .stdout(predicate::str::contains("export AWS_REGION=\"eu-west-1\""))
+                .stdout(predicate::str::contains(
+                    "export AWS_DEFAULT_REGION=\"eu-west-1\"",
+                ));
+            awsr(&["assume", "dev", "--exec", "env"])
+                .stdout(predicate::str::contains("AWS_REGION=eu-west-1"))
+                .stdout(predicate::str::contains(
+                    "AWS_ACCESS_KEY_ID=ASIACACHEDEXAMPLE",
+                ));
+        }
+    }
+
+    /// Test that GovCloud roles show their partition and reject commercial regions
+    #[test]
+    fn test_partition_region_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".aws-assume-role");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join("config.json"),
+            r#"{"default_region":"us-east-1","roles":[
+                {"name":"gov","role_arn":"arn:aws-us-gov:iam::123456789012:role/Admin","account_id":"123456789012","use_fips_endpoint":true}
+            ]}"#,
+        )
+        .unwrap();
+
+        let awsr = |args: &[&str]| {
+            let mut cmd = Command::cargo_bin("aws-assume-role").unwrap();
+            cmd.env("HOME", temp_dir.path());
+
+            // On Windows, also set USERPROFILE for proper home directory detection
+            #[cfg(windows)]
+            cmd.env("USERPROFILE", temp_dir.path());
+
+            cmd.args(args).assert()
+        };
+
+        awsr(&["list"])
+            .success()
+            .stdout(predicate::str::contains("Partition: aws-us-gov"));
+        awsr(&["assume", "gov", "--no-cache"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Role 'gov': Region 'us-east-1' belongs to partition 'aws', not 'aws-us-gov'",
+            ));
+    }
+
+    /// Test that assume runs end to end against a local STS stand-in
+    #[test]
+    fn test_assume_with_endpoint_url() {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(body_string_contains("Action=AssumeRole"))
+                .and(body_string_contains(
+                    "RoleArn=arn%3Aaws%3Aiam%3A%3A000000000000%3Arole%2FDev",
+                ))
+                .respond_with(common::assume_role_response("ASIALOCALSTACK", None))
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        common::write_config(
+            temp_dir.path(),
+            &format!(
+                r#"{{"endpoint_url":"{}","roles":[
+                    {{"name":"dev","role_arn":"arn:aws:iam::000000000000:role/Dev","account_id":"000000000000"}}
+                ]}}"#,
+                server.uri()
+            ),
+        );
+
+        common::awsr_cmd(temp_dir.path())
+            .args(["assume", "dev", "--format", "json", "--no-cache"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "\"AccessKeyId\": \"ASIALOCALSTACK\"",
+            ));
+    }
+
+    /// Test that session tags are rendered, merged with --tag and sent to STS
+    #[test]
+    fn test_assume_with_session_tags() {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(body_string_contains("Action=AssumeRole"))
+                .and(body_string_contains(
+                    "Tags.member.1.Key=owner&Tags.member.1.Value=alice",
+                ))
+                .and(body_string_contains(
+                    "Tags.member.2.Key=team&Tags.member.2.Value=platform",
+                ))
+                .and(body_string_contains(
+                    "Tags.member.3.Key=ticket&Tags.member.3.Value=OPS-42",
+                ))
+                .and(body_string_contains("TransitiveTagKeys.member.1=team"))
+                .respond_with(common::assume_role_response("ASIATAGGED", None))
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        common::write_config(
+            temp_dir.path(),
+            &format!(
+                r#"{{"endpoint_url":"{}","roles":[
+                    {{"name":"dev","role_arn":"arn:aws:iam::000000000000:role/Dev","account_id":"000000000000",
+                      "tags":{{"owner":"{{user}}","team":"platform"}},"transitive_tag_keys":["team"]}}
+                ]}}"#,
+                server.uri()
+            ),
+        );
+
+        let awsr = |args: &[&str]| {
+            common::awsr_cmd(temp_dir.path())
+                .env("USER", "alice")
+                .args(args)
+                .assert()
+        };
+
+        awsr(&[
+            "assume",
+            "dev",
+            "--format",
+            "json",
+            "--no-cache",
+            "--tag",
+            "ticket=OPS-42",
+        ])
+        .success()
+        .stdout(predicate::str::contains("\"AccessKeyId\": \"ASIATAGGED\""));
+        awsr(&["assume", "dev", "--no-cache", "--tag", "ticket=a,b"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Invalid session tag 'ticket': character ',' is not allowed",
+            ));
+    }
+
+    /// Test that session policies are validated, minified and sent to STS
+    #[test]
+    fn test_assume_with_session_policy() {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(body_string_contains("Action=AssumeRole"))
+                .and(body_string_contains(
+                    "Policy=%7B%22Statement%22%3A%5B%7B%22Action%22%3A%22s3%3AGetObject%22",
+                ))
+                .and(body_string_contains(
+                    "PolicyArns.member.1.arn=arn%3Aaws%3Aiam%3A%3Aaws%3Apolicy%2FReadOnlyAccess",
+                ))
+                .respond_with(common::assume_role_response("ASIASCOPED", None))
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let policy_path = temp_dir.path().join("policy.json");
+        fs::write(
+            &policy_path,
+            r#"{
+                "Statement": [{"Action": "s3:GetObject", "Effect": "Allow", "Resource": "*"}],
+                "Version": "2012-10-17"
+            }"#,
+        )
+        .unwrap();
+        common::write_config(
+            temp_dir.path(),
+            &format!(
+                r#"{{"endpoint_url":"{}","roles":[
+                    {{"name":"dev","role_arn":"arn:aws:iam::000000000000:role/Dev","account_id":"000000000000",
+                      "policy_arns":["arn:aws:iam::aws:policy/ReadOnlyAccess"]}}
+                ]}}"#,
+                server.uri()
+            ),
+        );
+
+        let awsr = |args: &[&str]| common::awsr_cmd(temp_dir.path()).args(args).assert();
+
+        let policy_arg = format!("@{}", policy_path.display());
+        awsr(&[
+            "assume",
+            "dev",
+            "--format",
+            "json",
+            "--no-cache",
+            "--policy",
+            &policy_arg,
+        ])
+        .success()
+        .stdout(predicate::str::contains("\"AccessKeyId\": \"ASIASCOPED\""))
+        .stderr(predicate::str::contains(
+            "Session narrowed by inline policy (@",
+        ));
+        awsr(&["assume", "dev", "--no-cache", "--policy", "{\"Version\":"])
+            .failure()
+            .stderr(predicate::str::contains("Invalid session policy:"));
+        awsr(&["list"]).success().stdout(predicate::str::contains(
+            "Session policy: arn:aws:iam::aws:policy/ReadOnlyAccess",
+        ));
+    }
+
+    /// Test that a configured SourceIdentity template is rendered and sent to STS
+    #[test]
+    fn test_assume_sets_source_identity() {
+        use wiremock::matchers::{body_string_contains, method};
+        use wiremock::{Mock, MockServer};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("POST"))
+                .and(body_string_contains("Action=AssumeRole"))
+                .and(body_string_contains("SourceIdentity=alice"))
+                .respond_with(common::assume_role_response(
+                    "ASIAIDENTIFIED",
+                    Some("alice"),
+                ))
+                .mount(&server)
+                .await;
+            server
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        common::write_config(
+            temp_dir.path(),
+            &format!(
+                r#"{{"endpoint_url":"{}","source_identity":"{{principal}}","roles":[
+                    {{"name":"dev","role_arn":"arn:aws:iam::000000000000:role/Dev","account_id":"000000000000"}},
+                    {{"name":"anonymous","role_arn":"arn:aws:iam::000000000000:role/Dev","account_id":"000000000000",
+                      "source_identity":"not valid!"}}
+                ]}}"#,
+                server.uri()
+            ),
+        );
+
+        let awsr = |args: &[&str]| {
+            common::awsr_cmd(temp_dir.path())
+                .env("USER", "alice")
+                .args(args)
+                .assert()
+        };
+
+        awsr(&["assume", "dev", "--format", "json", "--no-cache"])
+            .success()
+            .stdout(predicate::str::contains(
+                "\"AccessKeyId\": \"ASIAIDENTIFIED\"",
+            ));
+        awsr(&["assume", "anonymous", "--no-cache"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Invalid source identity 'not valid!'",
+            ));
+    }
+
+    /// Test that exec passes argv verbatim and exits with the child's code
+    #[cfg(unix)]
+    #[test]
+    fn test_exec_argv_and_exit_code() {
+        let temp_dir = TempDir::new().unwrap();
+        common::write_config(
+            temp_dir.path(),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012"
+                    }
+                ]
+            }"#,
+        );
+        common::write_cached_entry(
+            temp_dir.path(),
+            "dev",
+            "arn:aws:iam::123456789012:role/DevRole",
+        );
+
+        let awsr = |args: &[&str]| {
+            common::awsr_cmd(temp_dir.path())
+                .env("SHELL", "/bin/sh")
+                .args(args)
+                .assert()
+        };
+
+        awsr(&["exec", "dev", "--", "printf", "%s|", "my file", "$HOME"])
+            .success()
+            .stdout("my file|$HOME|");
+        awsr(&["exec", "dev", "--", "sh", "-c", "exit 42"]).code(42);
+        awsr(&[
+            "exec",
+            "dev",
+            "--shell",
+            "--",
+            "echo",
+            "$AWS_ACCESS_KEY_ID",
+            "| tr A-Z a-z",
+        ])
+        .success()
+        .stdout("asiacachedexample\n");
+        awsr(&[
+            "assume",
+            "dev",
+            "--exec",
+            "printf '%s' 'quoted arg'; exit 3",
+        ])
+        .code(3)
+        .stdout("quoted arg");
+        awsr(&["exec", "dev", "printf", "x"]).failure();
+    }
+
+    /// Test that exec replaces stale AWS variables and honours --env/--unset/--clean-env
+    #[cfg(unix)]
+    #[test]
+    fn test_exec_environment() {
+        let temp_dir = TempDir::new().unwrap();
+        common::write_config(
+            temp_dir.path(),
+            r#"{
+                "roles": [
+                    {
+                        "name": "dev",
+                        "role_arn": "arn:aws:iam::123456789012:role/DevRole",
+                        "account_id": "123456789012",
+                        "region": "eu-west-1"
+                    }
+                ]
+            }"#,
+        );
+        common::write_cached_entry(
+            temp_dir.path(),
+            "dev",
+            "arn:aws:iam::123456789012:role/DevRole",
+        );
+
+        let awsr = |args: &[&str]| {
+            common::awsr_cmd(temp_dir.path())
+                .env("AWS_PROFILE", "stale")
+                .env("AWS_SECURITY_TOKEN", "stale")
+                .env("EDITOR", "vi")
+                .args(args)
+                .assert()
+                .success()
+        };
+
+        awsr(&["exec", "dev", "--", "env"])
+            .stdout(predicate::str::contains(
+                "AWS_ACCESS_KEY_ID=ASIACACHEDEXAMPLE",
+            ))
+            .stdout(predicate::str::contains(
+                "AWS_CREDENTIAL_EXPIRATION=2100-01-01T00:00:00Z",
+            ))
+            .stdout(predicate::str::contains("AWS_REGION=eu-west-1"))
+            .stdout(predicate::str::contains("AWSR_ROLE=dev"))
+            .stdout(predicate::str::contains("EDITOR=vi"))
+            .stdout(predicate::str::contains("AWS_PROFILE").not())
+            .stdout(predicate::str::contains("AWS_SECURITY_TOKEN").not());
+        awsr(&[
+            "exec",
+            "dev",
+            "--clean-env",
+            "--env",
+            "TF_LOG=debug",
+            "--unset",
+            "AWS_DEFAULT_REGION",
+            "--",
+            "env",
+        ])
+        .stdout(predicate::str::contains("TF_LOG=debug"))
+        .stdout(predicate::str::contains("AWSR_ROLE=dev"))
+        .stdout(predicate::str::contains("EDITOR").not())
+        .stdout(predicate::str::contains("AWS_DEFAULT_REGION").not());
+        awsr(&["exec", "dev", "--server", "--", "env"])
+            .stdout(predicate::str::contains(
+                "AWS_CONTAINER_CREDENTIALS_FULL_URI=http://127.0.0.1:",
+            ))
+            .stdout(predicate::str::contains(
+                "AWS_CONTAINER_AUTHORIZATION_TOKEN=",
+            ))
+            .stdout(predicate::str::contains("AWSR_ROLE=dev"))
+            .stdout(predicate::str::contains("AWS_ACCESS_KEY_ID").not())
+            .stdout(predicate::str::contains("AWS_SESSION_TOKEN").not());
+    }
 }